@@ -1,40 +1,82 @@
+use crate::node::{Node, NodeType};
+use crate::references;
 use crate::rules::{Rule, RuleFilter};
-use crate::utilities::{is_tracking_image, repeat, trim_newlines};
+use crate::turndown::{CODE_BLOCK_END, CODE_BLOCK_START};
+use crate::utilities::{
+    collapse_whitespace, display_width, escape_link_text, escape_text, is_emoji_image,
+    is_tracking_image, parse_style, repeat, split_code_spans, trim_newlines, truncate_text,
+    unwrap_redirect,
+};
+use crate::{
+    CenterMode, IndentStyle, MarkColorMode, PreheaderMode, TurndownOptions, UnderlineMode,
+};
 use std::collections::HashMap;
 
 pub fn get_rules() -> HashMap<String, Rule> {
-    let mut rules = HashMap::new();
-
-    rules.insert("paragraph".to_string(), paragraph_rule());
-    rules.insert("lineBreak".to_string(), line_break_rule());
-    rules.insert("heading".to_string(), heading_rule());
-    rules.insert("blockquote".to_string(), blockquote_rule());
-    rules.insert("list".to_string(), list_rule());
-    rules.insert("listItem".to_string(), list_item_rule());
-    rules.insert("indentedCodeBlock".to_string(), indented_code_block_rule());
-    rules.insert("fencedCodeBlock".to_string(), fenced_code_block_rule());
-    rules.insert("horizontalRule".to_string(), horizontal_rule_rule());
-    rules.insert("inlineLink".to_string(), inline_link_rule());
-    rules.insert("referenceLink".to_string(), reference_link_rule());
-    rules.insert("emphasis".to_string(), emphasis_rule());
-    rules.insert("strong".to_string(), strong_rule());
-    rules.insert("code".to_string(), code_rule());
-    rules.insert("image".to_string(), image_rule());
-    rules.insert("comment".to_string(), comment_rule());
-    rules.insert(
-        "processingInstruction".to_string(),
-        processing_instruction_rule(),
-    );
-    rules.insert("style".to_string(), style_rule());
-    rules.insert("script".to_string(), script_rule());
-    rules.insert("hiddenPreheader".to_string(), hidden_preheader_rule());
-    rules.insert("superscript".to_string(), superscript_rule());
-    rules.insert("subscript".to_string(), subscript_rule());
-    rules.insert("listItemTableCell".to_string(), list_item_table_cell_rule());
-    rules.insert("listItemTableRow".to_string(), list_item_table_row_rule());
-    rules.insert("listItemPrefix".to_string(), list_item_prefix_rule());
-
-    rules
+    ordered_rules()
+        .into_iter()
+        .map(|(key, rule)| (key.to_string(), rule))
+        .collect()
+}
+
+/// Gets the built-in rules in the order they are checked, so that when several
+/// rules match a node the more specific one always wins
+pub fn ordered_rules() -> Vec<(&'static str, Rule)> {
+    vec![
+        // Content that is dropped wins over everything else
+        ("comment", comment_rule()),
+        ("processingInstruction", processing_instruction_rule()),
+        ("head", head_rule()),
+        ("document", document_rule()),
+        ("style", style_rule()),
+        ("script", script_rule()),
+        ("hiddenPreheader", hidden_preheader_rule()),
+        ("boilerplate", boilerplate_rule()),
+        // Markdown written by authors wins over every element rule
+        ("rawMarkdown", raw_markdown_rule()),
+        // Email list layouts built from tables
+        ("listItemPrefix", list_item_prefix_rule()),
+        ("listItemTableCell", list_item_table_cell_rule()),
+        ("listItemTableRow", list_item_table_row_rule()),
+        ("paragraph", paragraph_rule()),
+        ("lineBreak", line_break_rule()),
+        ("heading", heading_rule()),
+        ("blockquote", blockquote_rule()),
+        ("quoteAttribution", quote_attribution_rule()),
+        ("list", list_rule()),
+        ("listItem", list_item_rule()),
+        ("definitionList", definition_list_rule()),
+        ("definitionTerm", definition_term_rule()),
+        ("definitionDescription", definition_description_rule()),
+        ("indentedCodeBlock", indented_code_block_rule()),
+        ("fencedCodeBlock", fenced_code_block_rule()),
+        ("horizontalRule", horizontal_rule_rule()),
+        ("semanticTemplate", semantic_template_rule()),
+        ("blockLink", block_link_rule()),
+        ("inlineLink", inline_link_rule()),
+        ("referenceLink", reference_link_rule()),
+        ("emphasis", emphasis_rule()),
+        ("strong", strong_rule()),
+        ("code", code_rule()),
+        ("image", image_rule()),
+        ("caption", caption_rule()),
+        ("inlineQuote", inline_quote_rule()),
+        ("abbreviation", abbreviation_rule()),
+        ("font", font_rule()),
+        ("underline", underline_rule()),
+        ("highlight", highlight_rule()),
+        ("keyboard", keyboard_rule()),
+        ("sample", sample_rule()),
+        ("output", output_rule()),
+        ("superscript", superscript_rule()),
+        ("subscript", subscript_rule()),
+        ("bidiOverride", bidi_override_rule()),
+        ("svg", svg_rule()),
+        ("center", center_rule()),
+        ("fencedDiv", fenced_div_rule()),
+        ("formLabel", form_label_rule()),
+        ("formControl", form_control_rule()),
+    ]
 }
 
 fn comment_rule() -> Rule {
@@ -53,6 +95,44 @@ fn processing_instruction_rule() -> Rule {
     }
 }
 
+fn head_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("head".to_string()),
+        replacement: |_, node, options| {
+            if !options.title_as_heading {
+                return String::new();
+            }
+
+            let title = node
+                .children
+                .iter()
+                .find(|child| child.node_name == "TITLE")
+                .map(|title| escape_text(collapse_whitespace(&title.text_content()).trim()))
+                .unwrap_or_default();
+
+            if title.is_empty() {
+                String::new()
+            } else {
+                heading(&title, 1, options)
+            }
+        },
+    }
+}
+
+/// The <html> and <body> wrappers parsers add around every document, which are
+/// left out so the output starts the same with or without them
+fn document_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, _| is_document_wrapper(node)),
+        replacement: |content, _, _| content.to_string(),
+    }
+}
+
+/// Checks if a node is the <html> or <body> wrapper of a document
+pub(crate) fn is_document_wrapper(node: &Node) -> bool {
+    matches!(node.node_name.as_str(), "HTML" | "BODY")
+}
+
 fn style_rule() -> Rule {
     Rule {
         filter: RuleFilter::String("style".to_string()),
@@ -69,20 +149,16 @@ fn script_rule() -> Rule {
 
 fn hidden_preheader_rule() -> Rule {
     Rule {
-        filter: RuleFilter::Function(|node, _| {
-            node.node_name == "DIV"
-                && (node.get_attribute("data-email-preheader").is_some()
-                    || (node.get_attribute("style")
-                        .map(|s| {
-                            // Match visibility:hidden with height:0
-                            (s.contains("visibility:hidden") && s.contains("height:0"))
-                            // Also match display:none with overflow:hidden (common email preheader pattern)
-                            || (s.contains("display:none") && s.contains("overflow:hidden"))
-                        })
-                        .unwrap_or(false)
-                        && node.get_attribute("class")
-                            .map(|c| c.contains("h-0") && c.contains("opacity-0"))
-                            .unwrap_or(true)))
+        filter: RuleFilter::Function(|node, options| match options.preheader_detection {
+            PreheaderMode::Off => false,
+            PreheaderMode::Custom(is_preheader) => is_preheader(node),
+            PreheaderMode::Default => {
+                node.node_name == "DIV"
+                    && (node.get_attribute("data-email-preheader").is_some()
+                        || (is_hidden_preheader_style(node)
+                            && (node.get_attribute("class").is_none()
+                                || (node.has_class("h-0") && node.has_class("opacity-0")))))
+            }
         }),
         replacement: |_, _, _| {
             // Remove hidden preheader entirely - don't include in output
@@ -91,6 +167,95 @@ fn hidden_preheader_rule() -> Rule {
     }
 }
 
+/// Checks the inline style declarations for the usual ways of hiding a preheader
+fn is_hidden_preheader_style(node: &Node) -> bool {
+    let style = parse_style(&node.get_attribute("style").unwrap_or_default());
+    let is = |property: &str, value: &str| style.get(property).map(String::as_str) == Some(value);
+    let is_zero = |property: &str| {
+        style
+            .get(property)
+            .map_or(false, |value| matches!(value.as_str(), "0" | "0px"))
+    };
+
+    // Match visibility:hidden with height:0
+    (is("visibility", "hidden") && (is_zero("height") || is_zero("max-height")))
+        // Also match display:none with overflow:hidden (common email preheader pattern)
+        || (is("display", "none") && is("overflow", "hidden"))
+}
+
+fn raw_markdown_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, options| raw_markdown(node, options).is_some()),
+        replacement: |_, node, options| {
+            let markdown = raw_markdown(node, options).unwrap_or_default();
+            if node.is_block() {
+                format!("\n\n{}\n\n", trim_newlines(&markdown))
+            } else {
+                markdown
+            }
+        },
+    }
+}
+
+/// Gets the Markdown an element carrying the raw Markdown attribute is written as:
+/// the attribute's value, or the text of the element, whose whitespace the parser
+/// keeps as written
+pub(crate) fn raw_markdown(node: &Node, options: &TurndownOptions) -> Option<String> {
+    let attribute = options.raw_markdown_attribute.as_ref()?;
+    let value = node.get_attribute(attribute)?;
+    Some(if value.is_empty() {
+        node.text_content()
+    } else {
+        value
+    })
+}
+
+fn boilerplate_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, options| {
+            !options.boilerplate_patterns.is_empty()
+                && node.is_block()
+                && !is_document_wrapper(node)
+                && is_boilerplate(node, &options.boilerplate_patterns)
+        }),
+        replacement: |_, _, _| String::new(),
+    }
+}
+
+/// Checks if the class or id of a block, or the text it holds outside of nested
+/// blocks, contains any of the boilerplate patterns
+fn is_boilerplate(node: &Node, patterns: &[String]) -> bool {
+    let names = format!(
+        "{} {}",
+        node.get_attribute("class").unwrap_or_default(),
+        node.get_attribute("id").unwrap_or_default()
+    )
+    .to_lowercase();
+    let text = own_text(node)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+
+    patterns
+        .iter()
+        .map(|pattern| pattern.trim().to_lowercase())
+        .filter(|pattern| !pattern.is_empty())
+        .any(|pattern| names.contains(&pattern) || text.contains(&pattern))
+}
+
+/// Gets the text of a node, leaving out the text of nested block elements
+fn own_text(node: &Node) -> String {
+    node.children
+        .iter()
+        .map(|child| match child.node_type {
+            NodeType::Text => child.node_value.clone(),
+            NodeType::Element if !child.is_block() => own_text(child),
+            _ => String::new(),
+        })
+        .collect()
+}
+
 fn paragraph_rule() -> Rule {
     Rule {
         filter: RuleFilter::String("p".to_string()),
@@ -107,7 +272,14 @@ fn paragraph_rule() -> Rule {
 fn line_break_rule() -> Rule {
     Rule {
         filter: RuleFilter::String("br".to_string()),
-        replacement: |_, _, options| format!("{}\n", options.br),
+        replacement: |_, node, options| {
+            // Preformatted text keeps its line breaks as they are, without a hard break marker
+            if node.get_attribute("data-in-pre").is_some() {
+                "\n".to_string()
+            } else {
+                format!("{}\n", options.br)
+            }
+        },
     }
 }
 
@@ -127,26 +299,55 @@ fn heading_rule() -> Rule {
                 .chars()
                 .nth(1)
                 .and_then(|c| c.to_digit(10))
-                .unwrap_or(1) as usize;
+                .unwrap_or(1) as i32;
+            let h_level = h_level.saturating_add(options.heading_offset).clamp(1, 6) as usize;
 
-            if options.heading_style == crate::HeadingStyle::Setext && h_level < 3 {
-                let underline = repeat(if h_level == 1 { '=' } else { '-' }, content.len());
-                format!("\n\n{}\n{}\n\n", content, underline)
-            } else {
-                format!("\n\n{} {}\n\n", repeat('#', h_level), content)
-            }
+            heading(content, h_level, options)
         },
     }
 }
 
+/// Renders heading content at the given level using the configured heading style
+fn heading(content: &str, h_level: usize, options: &TurndownOptions) -> String {
+    if options.heading_style == crate::HeadingStyle::Setext && h_level < 3 {
+        // The underline spans the heading as displayed, so wide characters count twice
+        let width = content.lines().map(display_width).max().unwrap_or(0);
+        let underline = repeat(if h_level == 1 { '=' } else { '-' }, width);
+        format!("\n\n{}\n{}\n\n", content, underline)
+    } else {
+        format!("\n\n{} {}\n\n", repeat('#', h_level), content)
+    }
+}
+
 fn blockquote_rule() -> Rule {
     Rule {
         filter: RuleFilter::String("blockquote".to_string()),
-        replacement: |content, _, _| {
-            let trimmed = trim_newlines(content);
+        replacement: |content, node, options| {
+            let mut trimmed = trim_newlines(content).to_string();
+            if options.blockquote_cite {
+                if let Some(cite) = node
+                    .get_attribute("cite")
+                    .filter(|cite| !cite.trim().is_empty())
+                {
+                    let dash = if options.quote_attribution_dash {
+                        "— "
+                    } else {
+                        ""
+                    };
+                    trimmed.push_str(&format!("\n\n{}<{}>", dash, cite.trim()));
+                }
+            }
+            // Line breaks start new quoted lines, and a line holding nothing but a
+            // break is quoted as blank rather than keeping the break's spaces
             let quoted = trimmed
                 .lines()
-                .map(|line| format!("> {}", line))
+                .map(|line| {
+                    if line.trim().is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {}", line)
+                    }
+                })
                 .collect::<Vec<_>>()
                 .join("\n");
             format!("\n\n{}\n\n", quoted)
@@ -154,10 +355,36 @@ fn blockquote_rule() -> Rule {
     }
 }
 
+fn quote_attribution_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, _| {
+            (node.node_name == "FOOTER" && node.get_attribute("data-in-blockquote").is_some())
+                || node.get_attribute("data-quote-attribution").is_some()
+        }),
+        replacement: |content, _, options| {
+            let attribution = content.trim();
+            if attribution.is_empty() {
+                String::new()
+            } else if options.quote_attribution_dash {
+                format!("\n\n— {}\n\n", attribution)
+            } else {
+                format!("\n\n{}\n\n", attribution)
+            }
+        },
+    }
+}
+
 fn list_rule() -> Rule {
     Rule {
-        filter: RuleFilter::Array(vec!["ul".to_string(), "ol".to_string()]),
-        replacement: |content, _node, _| format!("\n\n{}\n\n", content),
+        filter: RuleFilter::Array(vec!["ul".to_string(), "ol".to_string(), "menu".to_string()]),
+        replacement: |content, node, _| {
+            // Nested lists sit directly under their parent item's text
+            if node.get_attribute("data-list-type").is_some() {
+                format!("\n{}\n", content)
+            } else {
+                format!("\n\n{}\n\n", content)
+            }
+        },
     }
 }
 
@@ -169,28 +396,121 @@ fn list_item_rule() -> Rule {
             let list_type = node.get_attribute("data-list-type");
             let list_index = node.get_attribute("data-list-index");
 
+            let spacing = repeat(' ', options.list_marker_spacing.max(1));
+
             if let (Some(list_type), Some(list_index_str)) = (list_type, list_index) {
                 if list_type == "OL" {
                     if let Ok(index) = list_index_str.parse::<usize>() {
-                        let prefix = format!("{}.  ", index);
-                        return format!("{}{}\n", prefix, content.trim_end());
+                        let prefix = format!("{}.{}", index, spacing);
+                        let indent = list_item_indent(&prefix, options);
+                        return format!("{}{}\n", prefix, indent_continuation(content, &indent));
                     }
                 }
             }
 
-            // Default to bullet list (bullet + 1 space)
-            let prefix = format!("{} ", options.bullet_list_marker);
-            format!("{}{}\n", prefix, content.trim_end())
+            // Default to bullet list
+            let marker = match node.get_attribute("data-list-style").as_deref() {
+                Some("disc") if options.list_style_markers => "*",
+                Some("circle") if options.list_style_markers => "-",
+                Some("square") if options.list_style_markers => "+",
+                _ => &options.bullet_list_marker,
+            };
+            let prefix = format!("{}{}", marker, spacing);
+            let indent = list_item_indent(&prefix, options);
+            format!("{}{}\n", prefix, indent_continuation(content, &indent))
+        },
+    }
+}
+
+fn definition_list_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("dl".to_string()),
+        replacement: |content, _, _| format!("\n\n{}\n\n", content.trim_matches('\n')),
+    }
+}
+
+fn definition_term_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("dt".to_string()),
+        replacement: |content, _, _| format!("\n{}\n", content.trim()),
+    }
+}
+
+fn definition_description_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("dd".to_string()),
+        // Definitions use the `:` marker of Pandoc and PHP Markdown Extra, with
+        // nested blocks such as another definition list indented beneath it
+        replacement: |content, _, _| {
+            let prefix = ":   ";
+            let content = content.trim();
+            // A blank line keeps the next term from continuing a multi-line definition
+            let end = if content.contains('\n') { "\n\n" } else { "\n" };
+            format!(
+                "\n{}{}{}",
+                prefix,
+                indent_continuation(content, &repeat(' ', prefix.len())),
+                end
+            )
         },
     }
 }
 
+/// Gets the indentation of the continuation lines of a list item: the width
+/// of its marker prefix, the configured number of spaces, or a tab
+fn list_item_indent(prefix: &str, options: &TurndownOptions) -> String {
+    match options.indent_style {
+        IndentStyle::MarkerWidth => repeat(' ', prefix.chars().count()),
+        IndentStyle::Spaces(width) => repeat(' ', width),
+        IndentStyle::Tab => "\t".to_string(),
+    }
+}
+
+/// Indents every line after the first, so nested blocks stay inside the item
+fn indent_continuation(content: &str, indent: &str) -> String {
+    content
+        .trim_end()
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 || line.is_empty() {
+                line.to_string()
+            } else {
+                format!("{}{}", indent, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn indented_code_block_rule() -> Rule {
     Rule {
         filter: RuleFilter::Function(|node, options| {
             options.code_block_style == crate::CodeBlockStyle::Indented && node.node_name == "PRE"
         }),
-        replacement: |content, _node, _| format!("\n\n{}\n\n", content),
+        replacement: |content, _node, options| {
+            let indent = match options.indent_style {
+                IndentStyle::MarkerWidth => repeat(' ', 4),
+                IndentStyle::Spaces(width) => repeat(' ', width),
+                IndentStyle::Tab => "\t".to_string(),
+            };
+            let code = content
+                .trim_end_matches('\n')
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{}{}", indent, line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            if code.is_empty() {
+                return "\n\n\n\n".to_string();
+            }
+            format!("\n\n{}{}{}\n\n", CODE_BLOCK_START, code, CODE_BLOCK_END)
+        },
     }
 }
 
@@ -199,14 +519,91 @@ fn fenced_code_block_rule() -> Rule {
         filter: RuleFilter::Function(|node, options| {
             options.code_block_style == crate::CodeBlockStyle::Fenced && node.node_name == "PRE"
         }),
-        replacement: |content, _node, options| {
+        replacement: |content, node, options| {
             let fence_char = options.fence.chars().next().unwrap_or('`');
             let fence = repeat(fence_char, 3);
-            format!("\n\n{}{}\n{}\n{}\n\n", fence, "", content.trim_end(), fence)
+            let language = code_language(node, options);
+            format!(
+                "\n\n{}{}\n{}\n{}\n\n",
+                fence,
+                language,
+                content.trim_end(),
+                fence
+            )
         },
     }
 }
 
+/// Gets the language of a code block for its fence: from a `language-*` or `lang-*`
+/// class on the block or its code element, then, as a last resort when enabled, from
+/// a `<!-- lang: x -->` comment or a shebang line at the top of the block
+fn code_language(node: &Node, options: &TurndownOptions) -> String {
+    let code = node.children.iter().find(|child| child.node_name == "CODE");
+    let from_class = [Some(node), code]
+        .into_iter()
+        .flatten()
+        .find_map(|element| {
+            let class = element.get_attribute("class")?;
+            class.split_whitespace().find_map(|token| {
+                let language = token
+                    .strip_prefix("language-")
+                    .or_else(|| token.strip_prefix("lang-"))?;
+                (!language.is_empty()).then(|| language.to_string())
+            })
+        });
+    if from_class.is_some() || !options.code_language_hints {
+        return from_class.unwrap_or_default();
+    }
+
+    let first = code
+        .and_then(first_content_child)
+        .or_else(|| first_content_child(node));
+    if let Some(comment) = first.filter(|first| first.node_type == NodeType::Comment) {
+        if let Some(language) = comment.node_value.trim().strip_prefix("lang:") {
+            return language.trim().to_string();
+        }
+    }
+
+    let text = node.text_content();
+    let shebang = match text
+        .trim_start()
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("#!"))
+    {
+        Some(shebang) => shebang,
+        None => return String::new(),
+    };
+    // `#!/usr/bin/env python3` names the interpreter after env, `#!/bin/bash` in the path
+    let mut words = shebang.split_whitespace();
+    let mut interpreter = words
+        .next()
+        .unwrap_or_default()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    if interpreter == "env" {
+        interpreter = words
+            .find(|word| !word.starts_with('-'))
+            .unwrap_or_default();
+    }
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match interpreter {
+        "sh" | "bash" | "dash" | "ksh" => "bash",
+        "node" | "nodejs" => "javascript",
+        interpreter => interpreter,
+    }
+    .to_string()
+}
+
+/// Gets the first child of an element that is not whitespace
+fn first_content_child(element: &Node) -> Option<&Node> {
+    element
+        .children
+        .iter()
+        .find(|child| !(child.node_type == NodeType::Text && child.node_value.trim().is_empty()))
+}
+
 fn horizontal_rule_rule() -> Rule {
     Rule {
         filter: RuleFilter::String("hr".to_string()),
@@ -214,6 +611,78 @@ fn horizontal_rule_rule() -> Rule {
     }
 }
 
+fn semantic_template_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, options| {
+            node.node_type == NodeType::Element && semantic_template(node, options).is_some()
+        }),
+        replacement: |content, node, options| {
+            let template = semantic_template(node, options).unwrap_or_default();
+            render_template(template, content.trim(), node)
+        },
+    }
+}
+
+/// Gets the configured template for the tag of an element
+fn semantic_template<'a>(node: &Node, options: &'a TurndownOptions) -> Option<&'a str> {
+    options
+        .semantic_templates
+        .iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case(&node.node_name))
+        .map(|(_, template)| template.as_str())
+}
+
+/// Fills the `{text}` and `{attribute}` placeholders of a template, leaving
+/// braces that do not close a placeholder as they are
+fn render_template(template: &str, text: &str, node: &Node) -> String {
+    let mut result = String::with_capacity(template.len() + text.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name = after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|name| !name.is_empty() && !name.contains(['{', ' ']));
+        match name {
+            Some(name) => {
+                if name == "text" {
+                    result.push_str(text);
+                } else {
+                    let value = node.get_attribute(&name.to_lowercase()).unwrap_or_default();
+                    result.push_str(&escape_text(&collapse_whitespace(value.trim())));
+                }
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn block_link_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, options| {
+            options.block_links_as_html
+                && node.node_name == "A"
+                && node.get_attribute("href").is_some()
+                && node.has_block_descendant()
+        }),
+        // The blank lines end the HTML block around the tags, so the content between stays Markdown
+        replacement: |content, node, _| {
+            format!(
+                "\n\n{}\n\n{}\n\n</a>\n\n",
+                node.to_open_tag(),
+                content.trim()
+            )
+        },
+    }
+}
+
 fn inline_link_rule() -> Rule {
     Rule {
         filter: RuleFilter::Function(|node, options| {
@@ -221,7 +690,7 @@ fn inline_link_rule() -> Rule {
                 && node.node_name == "A"
                 && node.get_attribute("href").is_some()
         }),
-        replacement: |content, node, _| {
+        replacement: |content, node, options| {
             let normalized_content = content
                 .trim()
                 .lines()
@@ -230,23 +699,85 @@ fn inline_link_rule() -> Rule {
                 .collect::<Vec<_>>()
                 .join(" ");
 
-            if normalized_content.starts_with('[') && normalized_content.contains("](") {
+            if options.drop_empty_links && normalized_content.is_empty() {
+                return String::new();
+            }
+
+            if let Some(html) = preserved_link_html(content, node, options) {
+                return html;
+            }
+
+            // Markdown links cannot nest, so the links inside are kept on their own
+            if has_nested_link(node) {
                 return normalized_content;
             }
 
-            let href = node.get_attribute("href").unwrap_or_default();
-            let href_escaped = href.replace("(", "\\(").replace(")", "\\)");
-            let title = node.get_attribute("title").unwrap_or_default();
-            let title_part = if !title.is_empty() {
-                format!(r#" "{}""#, title.replace("\"", "\\\""))
-            } else {
-                String::new()
-            };
+            let href = link_href(node, options);
+            let href_escaped = link_destination(&href);
+            let title_part = link_title(&node.get_attribute("title").unwrap_or_default());
             format!("[{}]({}{})", normalized_content, href_escaped, title_part)
         },
     }
 }
 
+/// Checks if a link holds another link, which a preprocessor or the simple
+/// parser can produce where HTML parsers close the outer one
+fn has_nested_link(link: &Node) -> bool {
+    let mut stack: Vec<&Node> = link.children.iter().collect();
+    while let Some(node) = stack.pop() {
+        if node.node_name == "A" && node.get_attribute("href").is_some() {
+            return true;
+        }
+        stack.extend(node.children.iter());
+    }
+    false
+}
+
+/// Renders a link as an HTML anchor around its converted content when it
+/// carries any of the attributes configured to be preserved
+fn preserved_link_html(content: &str, node: &Node, options: &TurndownOptions) -> Option<String> {
+    options
+        .preserve_link_attributes
+        .iter()
+        .any(|name| node.get_attribute(&name.to_lowercase()).is_some())
+        .then(|| format!("{}{}</a>", node.to_open_tag(), content.trim()))
+}
+
+/// Gets a link's href, unwrapping click-tracking redirects when enabled
+fn link_href(node: &Node, options: &TurndownOptions) -> String {
+    let href = node.get_attribute("href").unwrap_or_default();
+    if options.unwrap_tracking_redirects {
+        if let Some(url) = unwrap_redirect(&href, &options.redirect_url_params) {
+            return url;
+        }
+    }
+    href
+}
+
+/// Formats a link title to follow the destination, with a leading space, or
+/// nothing when the title is empty
+pub(crate) fn link_title(title: &str) -> String {
+    if title.is_empty() {
+        String::new()
+    } else {
+        format!(r#" "{}""#, title.replace('"', "\\\""))
+    }
+}
+
+/// Formats a link destination, wrapping it in angle brackets when it contains
+/// whitespace or control characters that would otherwise end the destination
+pub(crate) fn link_destination(href: &str) -> String {
+    if href.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        let encoded = href
+            .replace('<', "%3C")
+            .replace('>', "%3E")
+            .replace(['\n', '\r'], "");
+        format!("<{}>", encoded)
+    } else {
+        href.replace('(', "\\(").replace(')', "\\)")
+    }
+}
+
 fn reference_link_rule() -> Rule {
     Rule {
         filter: RuleFilter::Function(|node, options| {
@@ -254,10 +785,19 @@ fn reference_link_rule() -> Rule {
                 && node.node_name == "A"
                 && node.get_attribute("href").is_some()
         }),
-        replacement: |content, _node, options| match options.link_reference_style {
-            crate::LinkReferenceStyle::Collapsed => format!("{}[]", content),
-            crate::LinkReferenceStyle::Shortcut => format!("[{}]", content),
-            crate::LinkReferenceStyle::Full => format!("[{}][1]", content),
+        replacement: |content, node, options| {
+            if options.drop_empty_links && content.trim().is_empty() {
+                return String::new();
+            }
+
+            if let Some(html) = preserved_link_html(content, node, options) {
+                return html;
+            }
+
+            // Labels and definitions are added once the whole document is converted
+            let title = node.get_attribute("title").unwrap_or_default();
+            let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+            references::placeholder(content.trim(), &link_href(node, options), &title)
         },
     }
 }
@@ -265,13 +805,16 @@ fn reference_link_rule() -> Rule {
 fn emphasis_rule() -> Rule {
     Rule {
         filter: RuleFilter::Array(vec!["em".to_string(), "i".to_string()]),
-        replacement: |content, _, options| {
+        replacement: |content, node, options| {
             if content.trim().is_empty() {
                 String::new()
             } else {
-                format!(
-                    "{}{}{}",
-                    options.em_delimiter, content, options.em_delimiter
+                emphasize(
+                    content,
+                    node,
+                    options,
+                    &options.em_delimiter,
+                    "data-nested-emphasis",
                 )
             }
         },
@@ -281,13 +824,16 @@ fn emphasis_rule() -> Rule {
 fn strong_rule() -> Rule {
     Rule {
         filter: RuleFilter::Array(vec!["strong".to_string(), "b".to_string()]),
-        replacement: |content, _, options| {
+        replacement: |content, node, options| {
             if content.trim().is_empty() {
                 String::new()
             } else {
-                format!(
-                    "{}{}{}",
-                    options.strong_delimiter, content, options.strong_delimiter
+                emphasize(
+                    content,
+                    node,
+                    options,
+                    &options.strong_delimiter,
+                    "data-nested-strong",
                 )
             }
         },
@@ -328,21 +874,19 @@ fn image_rule() -> Rule {
             let width = node.get_attribute("width").unwrap_or_default();
             let height = node.get_attribute("height").unwrap_or_default();
 
-            // Always strip 1x1 pixel images without alt text (common tracking pixels)
-            if alt.trim().is_empty() && width == "1" && height == "1" {
-                return String::new();
+            match image_treatment(node, options) {
+                ImageTreatment::Emoji => return alt.trim().to_string(),
+                ImageTreatment::Pixel | ImageTreatment::Tracking | ImageTreatment::WithoutAlt => {
+                    return String::new()
+                }
+                ImageTreatment::Image => {}
             }
 
-            if options.strip_tracking_images
-                && is_tracking_image(
-                    &src,
-                    &alt,
-                    options.tracking_image_regex.as_ref(),
-                    options.strip_images_without_alt,
-                )
-            {
-                return String::new();
-            }
+            // Only the text is shortened, never the dimensions appended to it
+            let (alt, title) = match options.max_alt_length {
+                Some(max) => (truncate_text(&alt, max), truncate_text(&title, max)),
+                None => (alt, title),
+            };
 
             let title_part = if !title.is_empty() {
                 format!(r#" "{}""#, title)
@@ -350,8 +894,20 @@ fn image_rule() -> Rule {
                 String::new()
             };
 
+            let has_dimensions = !width.is_empty() && !height.is_empty();
+            let alt = if options.append_image_dimensions
+                && has_dimensions
+                && !(width == "1" && height == "1")
+            {
+                format!("{} ({}x{})", alt, width, height)
+                    .trim_start()
+                    .to_string()
+            } else {
+                alt
+            };
+
             if !src.is_empty() {
-                format!("![{}]({}{})", alt, src, title_part)
+                format!("![{}]({}{})", escape_link_text(&alt), src, title_part)
             } else {
                 String::new()
             }
@@ -359,6 +915,325 @@ fn image_rule() -> Rule {
     }
 }
 
+/// How the image rule renders an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ImageTreatment {
+    /// An emoji image, written as its alt text
+    Emoji,
+    /// A 1x1 image without alt text, always dropped
+    Pixel,
+    /// Dropped for matching the tracking regex or heuristics
+    Tracking,
+    /// Dropped for having no alt text
+    WithoutAlt,
+    /// Rendered as a Markdown image
+    Image,
+}
+
+/// Decides how the image rule renders an image
+pub(crate) fn image_treatment(node: &Node, options: &TurndownOptions) -> ImageTreatment {
+    let alt = node.get_attribute("alt").unwrap_or_default();
+    let src = node.get_attribute("src").unwrap_or_default();
+    let is_dimension = |name: &str| node.get_attribute(name).map_or(false, |value| value == "1");
+
+    if options.emoji_images_as_text
+        && !alt.trim().is_empty()
+        && is_emoji_image(node, &src, options.emoji_image_regex.as_ref())
+    {
+        return ImageTreatment::Emoji;
+    }
+
+    // Always strip 1x1 pixel images without alt text (common tracking pixels)
+    if alt.trim().is_empty() && is_dimension("width") && is_dimension("height") {
+        return ImageTreatment::Pixel;
+    }
+
+    if options.strip_tracking_images {
+        if is_tracking_image(&src, &alt, options.tracking_image_regex.as_ref(), false)
+            || options.tracking_heuristics.matches(&src)
+        {
+            return ImageTreatment::Tracking;
+        }
+        if options.strip_images_without_alt && alt.trim().is_empty() {
+            return ImageTreatment::WithoutAlt;
+        }
+    }
+
+    ImageTreatment::Image
+}
+
+fn caption_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Array(vec!["caption".to_string(), "figcaption".to_string()]),
+        replacement: |content, _, _| format!("\n\n{}\n\n", content.trim()),
+    }
+}
+
+fn inline_quote_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("q".to_string()),
+        replacement: |content, node, options| {
+            // Quotes nested in an odd number of others use single quotes
+            let mark = if node.get_attribute("data-nested-quote").is_some() {
+                '\''
+            } else {
+                '"'
+            };
+            let mut quote = format!("{}{}{}", mark, content, mark);
+
+            if options.quote_cite {
+                if let Some(cite) = node
+                    .get_attribute("cite")
+                    .filter(|cite| !cite.trim().is_empty())
+                {
+                    quote.push_str(&format!(" (<{}>)", cite.trim()));
+                }
+            }
+            quote
+        },
+    }
+}
+
+fn abbreviation_rule() -> Rule {
+    Rule {
+        // <acronym> is the deprecated predecessor of <abbr>
+        filter: RuleFilter::Array(vec!["abbr".to_string(), "acronym".to_string()]),
+        replacement: |content, _, _| content.to_string(),
+    }
+}
+
+fn underline_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("u".to_string()),
+        replacement: |content, node, options| {
+            if content.trim().is_empty() {
+                return content.to_string();
+            }
+
+            match options.underline_mode {
+                UnderlineMode::Drop => content.to_string(),
+                UnderlineMode::KeepHtml => format!("<u>{}</u>", content),
+                UnderlineMode::Emphasis => emphasize(
+                    content,
+                    node,
+                    options,
+                    &options.em_delimiter,
+                    "data-nested-emphasis",
+                ),
+            }
+        },
+    }
+}
+
+fn highlight_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("mark".to_string()),
+        replacement: |content, node, options| {
+            if content.trim().is_empty() {
+                return content.to_string();
+            }
+
+            let color = mark_color(node);
+            match (&options.mark_color, color) {
+                (MarkColorMode::Comment, Some(color)) => {
+                    format!("=={}==<!-- {} -->", content, color)
+                }
+                (MarkColorMode::KeepHtml, Some(_)) => {
+                    format!("{}{}</mark>", node.to_open_tag(), content)
+                }
+                _ => format!("=={}==", content),
+            }
+        },
+    }
+}
+
+/// Gets the color of a <mark> from its `data-color` attribute or background style
+fn mark_color(node: &Node) -> Option<String> {
+    let color = node.get_attribute("data-color").or_else(|| {
+        let style = parse_style(&node.get_attribute("style")?);
+        style
+            .get("background-color")
+            .or_else(|| style.get("background"))
+            .cloned()
+    })?;
+
+    // A color cannot end the comment it is written in
+    let color = color.trim().replace("--", "").replace('>', "");
+    (!color.is_empty()).then_some(color)
+}
+
+fn font_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("font".to_string()),
+        replacement: |content, node, options| {
+            if !options.font_as_html || content.trim().is_empty() {
+                return content.to_string();
+            }
+
+            let size = node.get_attribute("size").and_then(|size| {
+                let size = match size.trim() {
+                    "1" => "x-small",
+                    "2" => "small",
+                    "3" => "medium",
+                    "4" => "large",
+                    "5" => "x-large",
+                    "6" => "xx-large",
+                    "7" => "xxx-large",
+                    _ => return None,
+                };
+                Some(size.to_string())
+            });
+            let declarations: Vec<String> = [
+                ("color", node.get_attribute("color")),
+                ("font-size", size),
+                ("font-family", node.get_attribute("face")),
+            ]
+            .into_iter()
+            .filter_map(|(property, value)| {
+                let value = value?.trim().replace('"', "'");
+                (!value.is_empty()).then(|| format!("{}: {}", property, value))
+            })
+            .collect();
+
+            if declarations.is_empty() {
+                content.to_string()
+            } else {
+                format!(
+                    r#"<span style="{}">{}</span>"#,
+                    declarations.join("; "),
+                    content
+                )
+            }
+        },
+    }
+}
+
+fn keyboard_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("kbd".to_string()),
+        replacement: |content, node, options| match options.kbd_style {
+            crate::KbdStyle::Html => node.to_outer_html(),
+            crate::KbdStyle::Code => {
+                if content.trim().is_empty() {
+                    return String::new();
+                }
+                // Nested keys are flattened into a single combo such as `Ctrl+C`
+                code_span(&node.text_content())
+            }
+        },
+    }
+}
+
+fn sample_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("samp".to_string()),
+        replacement: |content, node, options| {
+            if options.samp_as_code && !content.trim().is_empty() {
+                code_span(&node.text_content())
+            } else {
+                content.to_string()
+            }
+        },
+    }
+}
+
+fn output_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("output".to_string()),
+        replacement: |content, node, options| {
+            if options.output_as_code && !content.trim().is_empty() {
+                code_span(&node.text_content())
+            } else {
+                content.to_string()
+            }
+        },
+    }
+}
+
+/// Wraps content in the delimiters of an emphasis span
+fn emphasize(
+    content: &str,
+    node: &Node,
+    options: &TurndownOptions,
+    delimiter: &str,
+    nested_attribute: &str,
+) -> String {
+    let mut delimiter = span_delimiter(delimiter, node, nested_attribute);
+    if options.emphasis_safety {
+        delimiter = safe_delimiter(delimiter, content, node);
+    }
+    format!("{}{}{}", delimiter, content, delimiter)
+}
+
+/// Swaps the underscores and asterisks of a delimiter when the content holds its
+/// character outside code spans, so the content cannot close the span early. It is
+/// kept when the content holds the other character too, or when the span sits
+/// inside a word, where underscores cannot open or close emphasis
+fn safe_delimiter(delimiter: String, content: &str, node: &Node) -> String {
+    let (marker, other) = match delimiter.chars().next() {
+        Some('_') => ('_', '*'),
+        Some('*') => ('*', '_'),
+        _ => return delimiter,
+    };
+    let is_intraword = node.get_attribute("data-intraword").is_some();
+    if !has_delimiter(content, marker)
+        || has_delimiter(content, other)
+        || (other == '_' && is_intraword)
+    {
+        return delimiter;
+    }
+    delimiter.replace(marker, &other.to_string())
+}
+
+/// Checks if Markdown holds an unescaped delimiter character outside code spans
+fn has_delimiter(markdown: &str, delimiter: char) -> bool {
+    split_code_spans(markdown)
+        .into_iter()
+        .filter(|(_, is_code)| !is_code)
+        .any(|(segment, _)| {
+            let mut is_escaped = false;
+            segment.chars().any(|c| {
+                let found = c == delimiter && !is_escaped;
+                is_escaped = c == '\\' && !is_escaped;
+                found
+            })
+        })
+}
+
+/// Gets the delimiter for an emphasis span. Spans nested in one of the same kind
+/// swap underscores and asterisks, as `__x__` would read as strong emphasis, and
+/// spans inside a word use asterisks, where underscores cannot open or close emphasis
+fn span_delimiter(delimiter: &str, node: &Node, nested_attribute: &str) -> String {
+    let delimiter = if node.get_attribute(nested_attribute).is_some() {
+        delimiter
+            .chars()
+            .map(|c| match c {
+                '_' => '*',
+                '*' => '_',
+                c => c,
+            })
+            .collect()
+    } else {
+        delimiter.to_string()
+    };
+
+    if node.get_attribute("data-intraword").is_some() {
+        delimiter.replace('_', "*")
+    } else {
+        delimiter
+    }
+}
+
+/// Renders text as a code span with its whitespace collapsed
+fn code_span(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.contains('`') {
+        format!("`` {} ``", text)
+    } else {
+        format!("`{}`", text)
+    }
+}
+
 fn superscript_rule() -> Rule {
     Rule {
         filter: RuleFilter::String("sup".to_string()),
@@ -367,7 +1242,7 @@ fn superscript_rule() -> Rule {
             if trimmed.is_empty() {
                 "<sup></sup>".to_string()
             } else {
-                format!("<sup>{}</sup> ", trimmed)
+                format!("<sup>{}</sup>", trimmed)
             }
         },
     }
@@ -381,12 +1256,171 @@ fn subscript_rule() -> Rule {
             if trimmed.is_empty() {
                 "<sub></sub>".to_string()
             } else {
-                format!("<sub>{}</sub> ", trimmed)
+                format!("<sub>{}</sub>", trimmed)
+            }
+        },
+    }
+}
+
+fn bidi_override_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("bdo".to_string()),
+        replacement: |content, node, options| {
+            if !options.bidi_override_controls {
+                return content.to_string();
+            }
+
+            // LEFT-TO-RIGHT / RIGHT-TO-LEFT OVERRIDE, closed by POP DIRECTIONAL FORMATTING
+            match node
+                .get_attribute("dir")
+                .map(|dir| dir.to_lowercase())
+                .as_deref()
+            {
+                Some("rtl") => format!("\u{202E}{}\u{202C}", content),
+                Some("ltr") => format!("\u{202D}{}\u{202C}", content),
+                _ => content.to_string(),
+            }
+        },
+    }
+}
+
+fn svg_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("svg".to_string()),
+        replacement: |_, node, options| {
+            if !options.svg_as_html {
+                return String::new();
+            }
+
+            // Kept on one line so blank lines in the source cannot end the HTML early
+            node.to_outer_html()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+    }
+}
+
+fn center_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, options| {
+            options.center_mode == CenterMode::KeepHtml
+                && (node.node_name == "CENTER"
+                    || (node.node_name == "DIV"
+                        && node
+                            .get_attribute("align")
+                            .map_or(false, |align| align.trim().eq_ignore_ascii_case("center"))))
+        }),
+        replacement: |content, node, _| {
+            let (open, close) = if node.node_name == "CENTER" {
+                ("<center>", "</center>")
+            } else {
+                (r#"<div align="center">"#, "</div>")
+            };
+            format!("\n\n{}\n\n{}\n\n{}\n\n", open, content.trim(), close)
+        },
+    }
+}
+
+fn fenced_div_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, options| {
+            options.pandoc_fenced_divs && node.node_name == "DIV" && first_class(node).is_some()
+        }),
+        // Opening fences carry an attribute, so nested divs need no longer fences
+        replacement: |content, node, _| {
+            let content = content.trim();
+            if content.is_empty() {
+                return "\n\n".to_string();
+            }
+            let class = first_class(node).unwrap_or_default();
+            format!("\n\n::: {}\n{}\n:::\n\n", class, content)
+        },
+    }
+}
+
+/// Gets the first class name of an element
+fn first_class(node: &Node) -> Option<String> {
+    node.get_attribute("class")?
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+}
+
+fn form_label_rule() -> Rule {
+    Rule {
+        // The text of a label paired with its control is rendered by the control
+        filter: RuleFilter::Function(|node, _| {
+            node.node_name == "LABEL" && node.get_attribute("data-merged-label").is_some()
+        }),
+        replacement: |_, _, _| String::new(),
+    }
+}
+
+fn form_control_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, _| {
+            matches!(node.node_name.as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+                && node.get_attribute("data-form-label").is_some()
+        }),
+        replacement: |_, node, _| {
+            let label = escape_text(&node.get_attribute("data-form-label").unwrap_or_default());
+            let input_type = node
+                .get_attribute("type")
+                .unwrap_or_default()
+                .to_lowercase();
+
+            let value = match node.node_name.as_str() {
+                "INPUT" => match input_type.as_str() {
+                    "hidden" => return String::new(),
+                    "checkbox" | "radio" => {
+                        let mark = if node.get_attribute("checked").is_some() {
+                            "x"
+                        } else {
+                            " "
+                        };
+                        return format!("[{}] {}", mark, label);
+                    }
+                    _ => node
+                        .get_attribute("value")
+                        .filter(|value| !value.trim().is_empty()),
+                },
+                "SELECT" => selected_option(node).map(|option| option.text_content()),
+                _ => Some(node.text_content()).filter(|value| !value.trim().is_empty()),
+            }
+            .or_else(|| node.get_attribute("placeholder"));
+
+            let value = value
+                .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+                .unwrap_or_default();
+            if value.is_empty() {
+                format!("{}:", label)
+            } else {
+                format!("{}: {}", label, escape_text(&value))
             }
         },
     }
 }
 
+/// Gets the selected option of a <select>, or its first option when none is selected
+fn selected_option(select: &Node) -> Option<&Node> {
+    let mut options = Vec::new();
+    let mut stack = vec![select];
+    while let Some(node) = stack.pop() {
+        if node.node_name == "OPTION" {
+            options.push(node);
+        } else {
+            stack.extend(node.children.iter().rev());
+        }
+    }
+
+    options
+        .iter()
+        .find(|option| option.get_attribute("selected").is_some())
+        .or_else(|| options.first())
+        .copied()
+}
+
 fn list_item_table_cell_rule() -> Rule {
     Rule {
         filter: RuleFilter::Function(|node, _| {
@@ -460,6 +1494,22 @@ fn list_item_prefix_rule() -> Rule {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_link_destination() {
+        assert_eq!(
+            link_destination("https://a.example/x(1)"),
+            "https://a.example/x\\(1\\)"
+        );
+        assert_eq!(
+            link_destination("https://a.example/my file.pdf"),
+            "<https://a.example/my file.pdf>"
+        );
+        assert_eq!(
+            link_destination("https://a.example/a b>c"),
+            "<https://a.example/a b%3Ec>"
+        );
+    }
+
     #[test]
     fn test_get_rules() {
         let rules = get_rules();
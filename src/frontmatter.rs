@@ -0,0 +1,104 @@
+//! YAML frontmatter collected from the document head
+//!
+//! The title, description, canonical URL and Open Graph properties of a page
+//! are read from its <head> and written as a frontmatter block, so converted
+//! pages keep their metadata when saved as Markdown files.
+
+use crate::node::{Node, NodeType};
+use crate::utilities::collapse_whitespace;
+
+/// Collects the metadata of the document head as frontmatter keys and values,
+/// in the order title, description, canonical URL, then Open Graph properties
+/// as they appear. The first of repeated keys is kept
+pub(crate) fn collect(root: &Node) -> Vec<(String, String)> {
+    let Some(head) = find_head(root) else {
+        return Vec::new();
+    };
+
+    let mut title = None;
+    let mut description = None;
+    let mut canonical = None;
+    let mut properties: Vec<(String, String)> = Vec::new();
+    for child in &head.children {
+        match child.node_name.as_str() {
+            "TITLE" => {
+                title = title.or_else(|| Some(child.text_content()));
+            }
+            "META" => {
+                let content = child.get_attribute("content").unwrap_or_default();
+                let name = child.get_attribute("name").unwrap_or_default();
+                let property = child.get_attribute("property").unwrap_or_default();
+                if name.eq_ignore_ascii_case("description") {
+                    description = description.or(Some(content));
+                } else if property.to_lowercase().starts_with("og:")
+                    && !properties.iter().any(|(key, _)| *key == property)
+                {
+                    properties.push((property, content));
+                }
+            }
+            "LINK" => {
+                let rel = child.get_attribute("rel").unwrap_or_default();
+                if rel
+                    .split_ascii_whitespace()
+                    .any(|token| token.eq_ignore_ascii_case("canonical"))
+                {
+                    canonical = canonical.or_else(|| child.get_attribute("href"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    [
+        ("title", title),
+        ("description", description),
+        ("canonical", canonical),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key.to_string(), value)))
+    .chain(properties)
+    .map(|(key, value)| (key, collapse_whitespace(&value).trim().to_string()))
+    .filter(|(_, value)| !value.is_empty())
+    .collect()
+}
+
+/// Writes the frontmatter block, with each value as a double-quoted YAML string
+pub(crate) fn render(entries: &[(String, String)]) -> String {
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| format!("{}: \"{}\"", key, escape_yaml(value)))
+        .collect();
+    format!("---\n{}\n---", lines.join("\n"))
+}
+
+/// Escapes a value for a double-quoted YAML string
+fn escape_yaml(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn find_head(node: &Node) -> Option<&Node> {
+    if node.node_name == "HEAD" {
+        return Some(node);
+    }
+    node.children
+        .iter()
+        .filter(|child| matches!(child.node_type, NodeType::Document | NodeType::Element))
+        .find_map(find_head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_quotes_values() {
+        let entries = vec![
+            ("title".to_string(), r#"Say "hi" \ bye"#.to_string()),
+            ("og:type".to_string(), "article".to_string()),
+        ];
+        assert_eq!(
+            render(&entries),
+            "---\ntitle: \"Say \\\"hi\\\" \\\\ bye\"\nog:type: \"article\"\n---"
+        );
+    }
+}
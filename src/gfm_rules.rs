@@ -0,0 +1,276 @@
+use crate::node::{Node, NodeType};
+use crate::rules::{Rule, RuleFilter};
+use crate::utilities::{escape_table_cell, split_code_spans};
+use crate::{ComplexTableMode, TurndownOptions};
+use std::collections::HashMap;
+
+/// Gets the GitHub Flavored Markdown rules, to be merged over the CommonMark rules
+pub fn get_rules() -> HashMap<String, Rule> {
+    ordered_rules()
+        .into_iter()
+        .map(|(key, rule)| (key.to_string(), rule))
+        .collect()
+}
+
+/// Gets the GitHub Flavored Markdown rules in the order they are checked
+pub fn ordered_rules() -> Vec<(&'static str, Rule)> {
+    vec![
+        ("table", table_rule()),
+        ("tableSection", table_section_rule()),
+        ("tableRow", table_row_rule()),
+        ("tableCell", table_cell_rule()),
+        ("tableLineBreak", table_line_break_rule()),
+    ]
+}
+
+fn table_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, options| {
+            node.node_name == "TABLE" && !is_layout_table(node, options)
+        }),
+        replacement: |content, node, options| {
+            if options.complex_table_mode == ComplexTableMode::KeepHtml && has_block_cells(node) {
+                return format!("\n\n{}\n\n", node.to_outer_html());
+            }
+
+            let columns = node.table_column_count();
+            if columns == 0 {
+                return format!("\n\n{}\n\n", content);
+            }
+
+            let mut rows = content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .peekable();
+            let mut blocks: Vec<String> = Vec::new();
+            let mut has_header = false;
+
+            while let Some(line) = rows.next() {
+                let is_row = line.starts_with('|');
+                let mut block = vec![line.to_string()];
+                while let Some(next) = rows.next_if(|next| next.starts_with('|') == is_row) {
+                    block.push(next.to_string());
+                }
+
+                if is_row && !has_header {
+                    // Pipe tables need a header row, so tables without one get an empty header
+                    if !has_heading_row(node, options) {
+                        block.insert(0, format!("|{}", "  |".repeat(columns)));
+                    }
+                    block.insert(1, format!("|{}", " --- |".repeat(columns)));
+                    has_header = true;
+                }
+                blocks.push(block.join("\n"));
+            }
+
+            format!("\n\n{}\n\n", blocks.join("\n\n"))
+        },
+    }
+}
+
+/// Checks if a table is used for layout rather than data. Tables with a
+/// presentation role always are, otherwise it is assumed when the table has a
+/// single column, no heading row or nested tables
+pub fn is_layout_table(table: &Node, options: &TurndownOptions) -> bool {
+    let role = table.get_attribute("role").unwrap_or_default();
+    if matches!(role.trim().to_lowercase().as_str(), "presentation" | "none") {
+        return true;
+    }
+
+    if !options.detect_layout_tables {
+        return false;
+    }
+
+    if table.table_column_count() <= 1 {
+        return true;
+    }
+
+    let rows = table.table_rows();
+    let mut cells: Vec<&Node> = rows
+        .iter()
+        .flat_map(|row| row.children.iter())
+        .filter(|cell| matches!(cell.node_name.as_str(), "TD" | "TH"))
+        .collect();
+    let has_heading = has_heading_row(table, options);
+
+    let mut has_nested_table = false;
+    while let Some(node) = cells.pop() {
+        if node.node_name == "TABLE" {
+            has_nested_table = true;
+            break;
+        }
+        cells.extend(node.children.iter());
+    }
+
+    !has_heading || has_nested_table
+}
+
+/// Checks if any cell of a table holds block content
+fn has_block_cells(table: &Node) -> bool {
+    table
+        .table_rows()
+        .iter()
+        .flat_map(|row| row.children.iter())
+        .any(|cell| matches!(cell.node_name.as_str(), "TD" | "TH") && cell.has_block_descendant())
+}
+
+/// Checks if the first row of a table is a heading row, either in a THEAD or made of TH cells.
+/// Empty rows are passed over when they are dropped
+fn has_heading_row(table: &Node, options: &TurndownOptions) -> bool {
+    let is_dropped = |row: &Node| options.drop_empty_table_rows && is_empty_row(row);
+    let first_section = table
+        .children
+        .iter()
+        .find(|child| match child.node_name.as_str() {
+            "TR" => !is_dropped(child),
+            "THEAD" | "TBODY" | "TFOOT" => !child.table_rows().into_iter().all(is_dropped),
+            _ => false,
+        });
+    if first_section.map_or(false, |section| section.node_name == "THEAD") {
+        return true;
+    }
+
+    let rows = table.table_rows();
+    rows.into_iter()
+        .find(|row| !is_dropped(row))
+        .map_or(false, |row| {
+            let mut cells = row
+                .children
+                .iter()
+                .filter(|cell| matches!(cell.node_name.as_str(), "TD" | "TH"))
+                .peekable();
+            cells.peek().is_some() && cells.all(|cell| cell.node_name == "TH")
+        })
+}
+
+/// Checks if a table row shows nothing, its cells holding only whitespace such as
+/// `&nbsp;` and elements without content of their own
+fn is_empty_row(row: &Node) -> bool {
+    if !row.text_content().trim().is_empty() {
+        return false;
+    }
+
+    let mut stack: Vec<&Node> = row.children.iter().collect();
+    while let Some(node) = stack.pop() {
+        let name = node.node_name.as_str();
+        let has_content = (node.is_void() && !matches!(name, "BR" | "WBR"))
+            || (node.is_meaningful_when_blank() && !matches!(name, "TD" | "TH"))
+            || matches!(name, "SVG" | "MATH" | "CANVAS");
+        if node.node_type == NodeType::Element && has_content {
+            return false;
+        }
+        stack.extend(node.children.iter());
+    }
+    true
+}
+
+fn table_section_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, _| {
+            matches!(node.node_name.as_str(), "THEAD" | "TBODY" | "TFOOT")
+                && node.get_attribute("data-table-columns").is_some()
+        }),
+        replacement: |content, _, _| format!("\n{}\n", content.trim_matches('\n')),
+    }
+}
+
+fn table_row_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, _| {
+            node.node_name == "TR" && node.get_attribute("data-table-columns").is_some()
+        }),
+        replacement: |content, node, options| {
+            if options.drop_empty_table_rows && is_empty_row(node) {
+                return String::new();
+            }
+
+            let columns: usize = node
+                .get_attribute("data-table-columns")
+                .and_then(|columns| columns.parse().ok())
+                .unwrap_or(0);
+            let cells: usize = node
+                .children
+                .iter()
+                .filter(|cell| matches!(cell.node_name.as_str(), "TD" | "TH"))
+                .map(Node::column_span)
+                .sum();
+
+            // Pad short rows so every row has the table's column count
+            let padding = "  |".repeat(columns.saturating_sub(cells));
+            format!("\n| {}{}\n", content.trim(), padding)
+        },
+    }
+}
+
+fn table_cell_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, _| {
+            matches!(node.node_name.as_str(), "TD" | "TH")
+                && node.get_attribute("data-table-columns").is_some()
+        }),
+        replacement: |content, node, _| {
+            // Blocks start new lines, which are kept apart by HTML line breaks
+            let text = if node.has_block_descendant() {
+                content
+                    .lines()
+                    .map(|line| escape_table_cell(&collapse_cell_whitespace(line)))
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("<br>")
+            } else {
+                escape_table_cell(&collapse_cell_whitespace(content))
+            };
+
+            // A cell spanning several columns is followed by empty cells
+            format!(" {} |{}", text, "  |".repeat(node.column_span() - 1))
+        },
+    }
+}
+
+/// Puts cell content on one line, collapsing whitespace runs outside code spans,
+/// whose spacing is kept apart from newlines, which a pipe table row cannot hold
+fn collapse_cell_whitespace(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    for (segment, is_code) in split_code_spans(content) {
+        if is_code {
+            result.push_str(&segment.replace('\n', " "));
+            continue;
+        }
+
+        let mut in_whitespace = false;
+        for ch in segment.chars() {
+            if ch.is_whitespace() {
+                if !in_whitespace {
+                    result.push(' ');
+                }
+                in_whitespace = true;
+            } else {
+                result.push(ch);
+                in_whitespace = false;
+            }
+        }
+    }
+    result.trim().to_string()
+}
+
+fn table_line_break_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::Function(|node, _| {
+            node.node_name == "BR" && node.get_attribute("data-in-table").is_some()
+        }),
+        // A pipe table cell has to stay on one line, so breaks are kept as HTML
+        replacement: |_, _, _| "<br>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_rules() {
+        let rules = get_rules();
+        assert!(rules.contains_key("table"));
+        assert!(rules.contains_key("tableCell"));
+    }
+}
@@ -1,17 +1,30 @@
+#[cfg(not(any(feature = "html5ever", feature = "simple-parser")))]
+compile_error!("either the `html5ever` or the `simple-parser` feature must be enabled");
+
 pub mod commonmark_rules;
+mod frontmatter;
+pub mod gfm_rules;
 pub mod node;
 pub mod parser;
+mod references;
 pub mod rules;
+#[cfg(feature = "simple-parser")]
+mod simple_parser;
 pub mod turndown;
 pub mod utilities;
 
 pub use node::{Node, NodeType};
 pub use rules::{Rule, RuleFilter, Rules};
 pub use turndown::{
-    CodeBlockStyle, HeadingStyle, LinkReferenceStyle, LinkStyle, Turndown, TurndownOptions,
+    CaptionPlacement, CenterMode, CodeBlockStyle, ComplexTableMode, ConvertStats, HeadingStyle,
+    IndentStyle, KbdStyle, LinkReferenceFormat, LinkReferenceStyle, LinkStyle, MarkColorMode,
+    ParserKind, PostProcessor, PreheaderMode, Preprocessor, ReferenceLabels, ReferenceOrder,
+    TrackingHeuristics, Turndown, TurndownOptions, TypographyMode, UnderlineMode, WhitespaceMode,
 };
 pub use utilities::{
-    clean_attribute, is_block, is_meaningful_when_blank, is_tracking_image, is_void, repeat,
-    trim_leading_newlines, trim_newlines, trim_trailing_newlines, FlankingWhitespace,
-    BLOCK_ELEMENTS, MEANINGFUL_WHEN_BLANK_ELEMENTS, VOID_ELEMENTS,
+    clean_attribute, display_width, escape_link_text, escape_table_cell, escape_text, is_block,
+    is_emoji_image, is_meaningful_when_blank, is_tracking_image, is_void, parse_style,
+    percent_decode, repeat, trim_leading_newlines, trim_newlines, trim_trailing_newlines,
+    typography_to_ascii, unwrap_redirect, FlankingWhitespace, BLOCK_ELEMENTS,
+    MEANINGFUL_WHEN_BLANK_ELEMENTS, VOID_ELEMENTS,
 };
@@ -1,6 +1,68 @@
-use crate::utilities::{is_block, is_meaningful_when_blank, is_void, FlankingWhitespace};
+use crate::utilities::{
+    is_block, is_meaningful_when_blank, is_void, parse_style, FlankingWhitespace,
+};
 use std::collections::HashMap;
 
+/// Attributes set on nodes during conversion to pass context to rules,
+/// never part of the source document
+pub const CONTEXT_ATTRIBUTES: &[&str] = &[
+    "data-list-type",
+    "data-list-index",
+    "data-list-style",
+    "data-in-pre",
+    "data-in-blockquote",
+    "data-table-columns",
+    "data-in-table",
+    "data-intraword",
+    "data-nested-emphasis",
+    "data-nested-strong",
+    "data-nested-quote",
+    "data-form-label",
+    "data-merged-label",
+    "data-quote-attribution",
+];
+
+/// SVG elements with mixed-case names, which are stored uppercase like any other element
+const SVG_MIXED_CASE_ELEMENTS: &[&str] = &[
+    "altGlyph",
+    "altGlyphDef",
+    "altGlyphItem",
+    "animateColor",
+    "animateMotion",
+    "animateTransform",
+    "clipPath",
+    "feBlend",
+    "feColorMatrix",
+    "feComponentTransfer",
+    "feComposite",
+    "feConvolveMatrix",
+    "feDiffuseLighting",
+    "feDisplacementMap",
+    "feDistantLight",
+    "feDropShadow",
+    "feFlood",
+    "feFuncA",
+    "feFuncB",
+    "feFuncG",
+    "feFuncR",
+    "feGaussianBlur",
+    "feImage",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+    "fePointLight",
+    "feSpecularLighting",
+    "feSpotLight",
+    "feTile",
+    "feTurbulence",
+    "foreignObject",
+    "glyphRef",
+    "linearGradient",
+    "radialGradient",
+    "textPath",
+];
+
 /// Represents different types of DOM nodes
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeType {
@@ -13,7 +75,7 @@ pub enum NodeType {
 
 /// Represents an HTML/DOM node with minimal stored state
 /// Computed properties are derived from node_name on-demand
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Node {
     pub node_type: NodeType,
     pub node_name: String,
@@ -89,6 +151,49 @@ impl Node {
         is_meaningful_when_blank(&self.node_name)
     }
 
+    /// Checks if this element preserves whitespace, either as a PRE element
+    /// or through a `white-space: pre` / `pre-wrap` inline style
+    pub fn is_preformatted(&self) -> bool {
+        if self.node_name == "PRE" {
+            return true;
+        }
+
+        self.get_attribute("style").map_or(false, |style| {
+            matches!(
+                parse_style(&style).get("white-space").map(String::as_str),
+                Some("pre" | "pre-wrap")
+            )
+        })
+    }
+
+    /// Gets the marker style of a list from the `list-style-type` (or
+    /// `list-style` shorthand) of its inline style, or its legacy `type` attribute
+    pub fn list_style_type(&self) -> Option<String> {
+        let from_style = self.get_attribute("style").and_then(|style| {
+            let declarations = parse_style(&style);
+            declarations.get("list-style-type").cloned().or_else(|| {
+                // The type is the shorthand keyword that is not a position or image
+                declarations.get("list-style").and_then(|shorthand| {
+                    shorthand
+                        .split_whitespace()
+                        .find(|word| {
+                            !matches!(*word, "inside" | "outside") && !word.starts_with("url(")
+                        })
+                        .map(str::to_string)
+                })
+            })
+        });
+
+        // CSS keywords ignore case, but `type="a"` and `type="A"` differ
+        from_style
+            .map(|style| style.trim().to_lowercase())
+            .or_else(|| {
+                self.get_attribute("type")
+                    .map(|kind| kind.trim().to_string())
+            })
+            .filter(|style| !style.is_empty())
+    }
+
     /// Calculates if node is blank (empty or only whitespace/void elements)
     pub fn is_blank(&self) -> bool {
         if self.is_meaningful_when_blank() {
@@ -96,13 +201,8 @@ impl Node {
         }
 
         // Void elements with meaningful attributes are never blank
-        if self.is_void() {
-            if self.get_attribute("src").is_some()
-                || self.get_attribute("data").is_some()
-                || matches!(self.node_name.as_str(), "BR" | "HR")
-            {
-                return false;
-            }
+        if self.is_meaningful_void() {
+            return false;
         }
 
         let has_text_content = self
@@ -111,23 +211,14 @@ impl Node {
             .any(|child| child.node_type == NodeType::Text && !child.node_value.trim().is_empty());
 
         // Check if there are any void elements with meaningful attributes
-        let has_meaningful_void_children = self.children.iter().any(|child| {
-            child.node_type == NodeType::Element
-                && child.is_void()
-                && (child.get_attribute("src").is_some()
-                    || child.get_attribute("data").is_some()
-                    || matches!(child.node_name.as_str(), "BR" | "HR"))
-        });
+        let has_meaningful_void_children = self
+            .children
+            .iter()
+            .any(|child| child.node_type == NodeType::Element && child.is_meaningful_void());
 
         // Element is blank if it has no text content AND no meaningful void children
         let has_only_empty_void_children = self.children.iter().all(|child| {
-            if child.node_type == NodeType::Element && child.is_void() {
-                child.get_attribute("src").is_none()
-                    && child.get_attribute("data").is_none()
-                    && !matches!(child.node_name.as_str(), "BR" | "HR")
-            } else {
-                false
-            }
+            child.node_type == NodeType::Element && child.is_void() && !child.is_meaningful_void()
         });
 
         !has_text_content
@@ -135,7 +226,19 @@ impl Node {
             && (has_only_empty_void_children || self.children.is_empty())
     }
 
-    /// Gets the flanking whitespace (leading/trailing whitespace)
+    /// Checks if this is a void element that renders something: a line break or
+    /// rule, media with a source, or a form control with an associated label
+    fn is_meaningful_void(&self) -> bool {
+        self.is_void()
+            && (self.get_attribute("src").is_some()
+                || self.get_attribute("data").is_some()
+                || self.get_attribute("data-form-label").is_some()
+                || matches!(self.node_name.as_str(), "BR" | "HR"))
+    }
+
+    /// Gets the flanking whitespace (leading/trailing whitespace) of the text at
+    /// the edges of an element, looking into the inline elements it starts or
+    /// ends with. A lone whitespace-only text is only leading, like a single space
     pub fn flanking_whitespace(&self) -> FlankingWhitespace {
         if self.node_type != NodeType::Element {
             return FlankingWhitespace::new(String::new(), String::new());
@@ -144,29 +247,42 @@ impl Node {
         let mut leading = String::new();
         let mut trailing = String::new();
 
-        // Check for leading whitespace in first text child
-        if let Some(first_child) = self.children.first() {
-            if first_child.node_type == NodeType::Text {
-                let text = &first_child.node_value;
-                let trimmed = text.trim_start();
-                if trimmed.len() < text.len() {
-                    leading = text[..(text.len() - trimmed.len())].to_string();
-                }
+        let first = self.edge_text(|children| children.first());
+        let last = self.edge_text(|children| children.last());
+        // The edges are split off around the trimmed text rather than at byte offsets,
+        // which keeps multibyte whitespace such as U+3000 whole
+        if let Some(text) = first.map(|node| &node.node_value) {
+            leading = text
+                .strip_suffix(text.trim_start())
+                .unwrap_or_default()
+                .to_string();
+        }
+        if let Some(node) = last {
+            let text = &node.node_value;
+            let trimmed = text.trim_end();
+            let is_only_space =
+                trimmed.is_empty() && first.map_or(false, |first| std::ptr::eq(first, node));
+            if !is_only_space {
+                trailing = text.strip_prefix(trimmed).unwrap_or_default().to_string();
             }
         }
 
-        // Check for trailing whitespace in last text child
-        if let Some(last_child) = self.children.last() {
-            if last_child.node_type == NodeType::Text {
-                let text = &last_child.node_value;
-                let trimmed = text.trim_end();
-                if trimmed.len() < text.len() {
-                    trailing = text[trimmed.len()..].to_string();
+        FlankingWhitespace::new(leading, trailing)
+    }
+
+    /// Gets the text at an edge of an element, following the first or last
+    /// child through nested inline elements
+    fn edge_text(&self, edge: fn(&[Node]) -> Option<&Node>) -> Option<&Node> {
+        let mut node = edge(&self.children)?;
+        loop {
+            match node.node_type {
+                NodeType::Text => return Some(node),
+                NodeType::Element if !node.is_block() && !node.is_void() => {
+                    node = edge(&node.children)?;
                 }
+                _ => return None,
             }
         }
-
-        FlankingWhitespace::new(leading, trailing)
     }
 
     /// Gets an attribute value
@@ -174,6 +290,89 @@ impl Node {
         self.attributes.get(name).cloned()
     }
 
+    /// Finds the first element in the subtree, this node included, with the given id
+    pub fn find_by_id(&self, id: &str) -> Option<&Node> {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.node_type == NodeType::Element
+                && node.attributes.get("id").map_or(false, |value| value == id)
+            {
+                return Some(node);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        None
+    }
+
+    /// Checks if any descendant of this node is a block-level element
+    pub fn has_block_descendant(&self) -> bool {
+        let mut stack: Vec<&Node> = self.children.iter().collect();
+        while let Some(node) = stack.pop() {
+            if node.node_type == NodeType::Element && node.is_block() {
+                return true;
+            }
+            stack.extend(node.children.iter());
+        }
+        false
+    }
+
+    /// Checks if the class attribute contains the given class token
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attributes.get("class").map_or(false, |classes| {
+            classes.split_whitespace().any(|c| c == class)
+        })
+    }
+
+    /// Gets the rows of a table in document order, including those in its
+    /// THEAD, TBODY and TFOOT sections but not those of nested tables
+    pub fn table_rows(&self) -> Vec<&Node> {
+        let mut rows = Vec::new();
+        for child in &self.children {
+            match child.node_name.as_str() {
+                "TR" => rows.push(child),
+                "THEAD" | "TBODY" | "TFOOT" => rows.extend(child.table_rows()),
+                _ => {}
+            }
+        }
+        rows
+    }
+
+    /// Gets the number of columns this element spans, from its colspan or span
+    /// attribute, capped at the 1000 columns HTML allows
+    pub fn column_span(&self) -> usize {
+        self.get_attribute("colspan")
+            .or_else(|| self.get_attribute("span"))
+            .and_then(|span| span.trim().parse().ok())
+            .filter(|span| *span > 0)
+            .map_or(1, |span: usize| span.min(1000))
+    }
+
+    /// Gets the number of columns of a table, the widest of its rows or its
+    /// COL declarations
+    pub fn table_column_count(&self) -> usize {
+        let row_columns = self.table_rows().into_iter().map(|row| {
+            row.children
+                .iter()
+                .filter(|cell| matches!(cell.node_name.as_str(), "TD" | "TH"))
+                .map(Node::column_span)
+                .sum::<usize>()
+        });
+        let declared_columns: usize = self
+            .children
+            .iter()
+            .filter(|child| child.node_name == "COLGROUP")
+            .map(|group| {
+                let cols = group.children.iter().filter(|col| col.node_name == "COL");
+                match cols.map(Node::column_span).sum() {
+                    0 => group.column_span(),
+                    count => count,
+                }
+            })
+            .sum();
+
+        row_columns.max().unwrap_or(0).max(declared_columns)
+    }
+
     /// Sets an attribute value
     pub fn set_attribute(&mut self, name: &str, value: &str) {
         self.attributes.insert(name.to_string(), value.to_string());
@@ -189,6 +388,45 @@ impl Node {
         !self.children.is_empty()
     }
 
+    /// Gets the number of levels of the subtree below this node, without recursion
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut stack = vec![(self, 0)];
+        while let Some((node, level)) = stack.pop() {
+            depth = depth.max(level);
+            stack.extend(node.children.iter().map(|child| (child, level + 1)));
+        }
+        depth
+    }
+
+    /// Checks if the subtree renders nothing visible: text made only of whitespace,
+    /// NBSP or zero-width characters, and no media or other meaningful elements
+    pub fn is_spacer(&self) -> bool {
+        let mut stack: Vec<&Node> = self.children.iter().collect();
+        while let Some(node) = stack.pop() {
+            match node.node_type {
+                NodeType::Text => {
+                    let is_blank = node.node_value.chars().all(|c| {
+                        c.is_whitespace()
+                            || matches!(c, '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{FEFF}')
+                    });
+                    if !is_blank {
+                        return false;
+                    }
+                }
+                NodeType::Element => {
+                    let is_break = matches!(node.node_name.as_str(), "BR" | "WBR");
+                    if node.is_meaningful_when_blank() || (node.is_void() && !is_break) {
+                        return false;
+                    }
+                    stack.extend(node.children.iter());
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
     /// Gets the text content recursively
     pub fn text_content(&self) -> String {
         match self.node_type {
@@ -203,22 +441,53 @@ impl Node {
         }
     }
 
+    /// Gets the tag name as written in HTML, restoring the case of SVG elements
+    fn tag_name(&self) -> String {
+        SVG_MIXED_CASE_ELEMENTS
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(&self.node_name))
+            .map_or_else(|| self.node_name.to_lowercase(), |name| name.to_string())
+    }
+
+    /// Renders the opening tag of an element with its source attributes in name order
+    pub fn to_open_tag(&self) -> String {
+        let mut attributes: Vec<_> = self
+            .attributes
+            .iter()
+            .filter(|(key, _)| !CONTEXT_ATTRIBUTES.contains(&key.as_str()))
+            .collect();
+        attributes.sort();
+
+        let mut html = format!("<{}", self.tag_name());
+        for (key, value) in attributes {
+            if value.is_empty() {
+                html.push_str(&format!(" {}", key));
+            } else {
+                html.push_str(&format!(r#" {}="{}""#, key, escape_attribute_value(value)));
+            }
+        }
+        html.push('>');
+        html
+    }
+
     /// Converts node to outer HTML representation
     pub fn to_outer_html(&self) -> String {
         match self.node_type {
             NodeType::Element => {
-                let mut html = format!("<{}", self.node_name.to_lowercase());
-                for (key, value) in &self.attributes {
-                    html.push_str(&format!(r#" {}="{}""#, key, value));
-                }
-                html.push('>');
+                let mut html = self.to_open_tag();
 
+                // Text is escaped so it stays text, except in raw text elements
+                let is_raw_text = matches!(self.node_name.as_str(), "SCRIPT" | "STYLE");
                 for child in &self.children {
-                    html.push_str(&child.to_outer_html());
+                    if child.node_type == NodeType::Text && !is_raw_text {
+                        html.push_str(&escape_html_text(&child.node_value));
+                    } else {
+                        html.push_str(&child.to_outer_html());
+                    }
                 }
 
                 if !self.is_void() {
-                    html.push_str(&format!("</{}>", self.node_name.to_lowercase()));
+                    html.push_str(&format!("</{}>", self.tag_name()));
                 }
                 html
             }
@@ -230,6 +499,22 @@ impl Node {
     }
 }
 
+/// Escapes the characters that would otherwise be read as markup in HTML text
+fn escape_html_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escapes the characters of a double-quoted HTML attribute value that would
+/// otherwise end the value or be read as markup
+fn escape_attribute_value(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -274,6 +559,100 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_is_preformatted() {
+        assert!(Node::new_element("pre").is_preformatted());
+
+        let mut node = Node::new_element("div");
+        assert!(!node.is_preformatted());
+        node.set_attribute("style", "font-family: monospace; WHITE-SPACE: pre-wrap");
+        assert!(node.is_preformatted());
+        node.set_attribute("style", "white-space: nowrap");
+        assert!(!node.is_preformatted());
+    }
+
+    #[test]
+    fn test_flanking_whitespace_keeps_multibyte_characters() {
+        let mut node = Node::new_element("em");
+        node.add_child(Node::new_text("\u{3000}\u{a0}b\u{2003}"));
+        let flanking = node.flanking_whitespace();
+        assert_eq!(flanking.leading, "\u{3000}\u{a0}");
+        assert_eq!(flanking.trailing, "\u{2003}");
+
+        let mut node = Node::new_element("em");
+        node.add_child(Node::new_text("\u{3000}"));
+        node.add_child(Node::new_text("中\u{205f}"));
+        let flanking = node.flanking_whitespace();
+        assert_eq!(flanking.leading, "\u{3000}");
+        assert_eq!(flanking.trailing, "\u{205f}");
+    }
+
+    #[test]
+    fn test_outer_html_restores_svg_case() {
+        let mut svg = Node::new_element("svg");
+        svg.set_attribute("viewBox", "0 0 10 10");
+        svg.add_child(Node::new_element("linearGradient"));
+        assert_eq!(
+            svg.to_outer_html(),
+            r#"<svg viewBox="0 0 10 10"><linearGradient></linearGradient></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_to_open_tag() {
+        let mut node = Node::new_element("a");
+        node.set_attribute("title", r#"Say "hi""#);
+        node.set_attribute("href", "https://example.com/?a=1&b=<2>");
+        node.set_attribute("download", "");
+        node.set_attribute("data-list-type", "UL");
+        assert_eq!(
+            node.to_open_tag(),
+            r#"<a download href="https://example.com/?a=1&amp;b=&lt;2>" title="Say &quot;hi&quot;">"#
+        );
+    }
+
+    #[test]
+    fn test_table_column_count() {
+        let row = |cells: &[(&str, Option<&str>)]| {
+            let mut row = Node::new_element("tr");
+            for (name, colspan) in cells {
+                let mut cell = Node::new_element(name);
+                if let Some(colspan) = colspan {
+                    cell.set_attribute("colspan", colspan);
+                }
+                row.add_child(cell);
+            }
+            row
+        };
+
+        let mut body = Node::new_element("tbody");
+        body.add_child(row(&[("td", None), ("td", Some("2"))]));
+        body.add_child(row(&[("td", None)]));
+        let mut table = Node::new_element("table");
+        table.add_child(row(&[("th", None), ("th", None)]));
+        table.add_child(body);
+
+        assert_eq!(table.table_rows().len(), 3);
+        assert_eq!(table.table_column_count(), 3);
+
+        let mut colgroup = Node::new_element("colgroup");
+        colgroup.set_attribute("span", "4");
+        table.children.insert(0, colgroup);
+        assert_eq!(table.table_column_count(), 4);
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut inner = Node::new_element("span");
+        inner.add_child(Node::new_text("deep"));
+        let mut outer = Node::new_element("div");
+        outer.add_child(inner);
+        outer.add_child(Node::new_text("shallow"));
+
+        assert_eq!(outer.depth(), 2);
+        assert_eq!(Node::new_text("leaf").depth(), 0);
+    }
+
     #[test]
     fn test_text_content() {
         let mut parent = Node::new_element("p");
@@ -282,6 +661,63 @@ mod tests {
         assert_eq!(parent.text_content(), "Hello World");
     }
 
+    #[test]
+    fn test_is_spacer() {
+        let mut item = Node::new_element("li");
+        let mut span = Node::new_element("span");
+        span.add_child(Node::new_text("\u{a0} \u{200b}"));
+        item.add_child(span);
+        item.add_child(Node::new_element("br"));
+        assert!(item.is_spacer());
+
+        item.add_child(Node::new_element("img"));
+        assert!(!item.is_spacer());
+
+        let mut text_item = Node::new_element("li");
+        text_item.add_child(Node::new_text("\u{a0}Item"));
+        assert!(!text_item.is_spacer());
+    }
+
+    #[test]
+    fn test_list_style_type() {
+        let mut list = Node::new_element("ul");
+        assert_eq!(list.list_style_type(), None);
+
+        list.set_attribute("type", "square");
+        assert_eq!(list.list_style_type().as_deref(), Some("square"));
+
+        list.set_attribute("style", "margin: 0; list-style: Circle inside");
+        assert_eq!(list.list_style_type().as_deref(), Some("circle"));
+
+        list.set_attribute("style", "list-style-type: disc");
+        assert_eq!(list.list_style_type().as_deref(), Some("disc"));
+
+        let mut ordered = Node::new_element("ol");
+        ordered.set_attribute("type", "A");
+        assert_eq!(ordered.list_style_type().as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn test_find_by_id() {
+        let mut form = Node::new_element("form");
+        let mut label = Node::new_element("label");
+        label.set_attribute("id", "name-label");
+        let mut input = Node::new_element("input");
+        input.set_attribute("id", "name");
+        label.add_child(input);
+        form.add_child(label);
+
+        assert_eq!(
+            form.find_by_id("name").map(|n| n.node_name.as_str()),
+            Some("INPUT")
+        );
+        assert_eq!(
+            form.find_by_id("name-label").map(|n| n.node_name.as_str()),
+            Some("LABEL")
+        );
+        assert!(form.find_by_id("missing").is_none());
+    }
+
     #[test]
     fn test_is_blank() {
         let node = Node::new_element("div");
@@ -1,69 +1,319 @@
-use crate::node::Node;
-#[cfg(test)]
-use crate::node::NodeType;
+use crate::node::{Node, NodeType};
+#[cfg(feature = "simple-parser")]
+use crate::simple_parser::{self, RawNode};
+use crate::utilities::{collapse_whitespace, parse_style};
+use crate::{ParserKind, TurndownOptions, WhitespaceMode};
+#[cfg(feature = "html5ever")]
 use html5ever::parse_document;
+#[cfg(feature = "html5ever")]
 use html5ever::tendril::TendrilSink;
+#[cfg(feature = "html5ever")]
 use markup5ever_rcdom::{Handle, NodeData, RcDom};
+#[cfg(feature = "html5ever")]
 use std::default::Default;
 
-/// Parses HTML string into a Node tree using html5ever
+/// Parses HTML string into a Node tree using the default parser
 pub fn parse_html(html: &str) -> Node {
-    let dom = parse_document(RcDom::default(), Default::default())
-        .from_utf8()
-        .read_from(&mut html.as_bytes())
-        .unwrap();
+    parse_html_with_options(html, &TurndownOptions::default())
+}
+
+/// Parses HTML string into a Node tree with the configured parser, handling
+/// newlines in text per the whitespace mode and flattening elements nested
+/// deeper than the maximum depth
+pub fn parse_html_with_options(html: &str, options: &TurndownOptions) -> Node {
+    let context = ParseContext {
+        in_code: false,
+        in_pre: false,
+        preserve_lines: options.whitespace_mode == WhitespaceMode::PreserveLineBreaks,
+        in_foreign: false,
+        depth: 0,
+        max_depth: options.max_depth,
+        raw_markdown_attribute: options.raw_markdown_attribute.as_deref(),
+    };
+
+    match options.parser {
+        #[cfg(feature = "html5ever")]
+        ParserKind::Html5ever => {
+            let dom = parse_document(RcDom::default(), Default::default())
+                .from_utf8()
+                .read_from(&mut html.as_bytes())
+                // Reading from memory cannot fail, but an empty tree beats a panic
+                .unwrap_or_else(|_| RcDom::default());
+            convert_node(&dom.document, context)
+        }
+        #[cfg(feature = "simple-parser")]
+        ParserKind::Simple => {
+            let document = simple_parser::parse(html);
+            convert_node(&&document, context)
+        }
+    }
+}
+
+/// Context inherited from ancestor elements while converting
+#[derive(Clone, Copy)]
+struct ParseContext<'a> {
+    /// Inside a CODE element
+    in_code: bool,
+    /// Inside a PRE block or an element styled to preserve whitespace
+    in_pre: bool,
+    /// Newlines in text are kept as line breaks
+    preserve_lines: bool,
+    /// Inside an SVG or MathML element
+    in_foreign: bool,
+    /// Number of ancestor elements
+    depth: usize,
+    max_depth: usize,
+    /// Attribute marking elements whose text is raw Markdown, kept as written
+    raw_markdown_attribute: Option<&'a str>,
+}
+
+/// The data of a node in a parsed document, whichever parser built it
+enum SourceData {
+    Document,
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    Text(String),
+    Comment(String),
+    Other,
+}
+
+/// A node of the document tree produced by a parser
+trait SourceNode: Sized {
+    fn data(&self) -> SourceData;
+    fn child_nodes(&self) -> Vec<Self>;
+}
+
+#[cfg(feature = "html5ever")]
+impl SourceNode for Handle {
+    fn data(&self) -> SourceData {
+        match &self.data {
+            NodeData::Document => SourceData::Document,
+            NodeData::Element { name, attrs, .. } => SourceData::Element {
+                name: name.local.to_string(),
+                attributes: attrs
+                    .borrow()
+                    .iter()
+                    .map(|attr| {
+                        // Namespaced attributes such as xlink:href keep their prefix
+                        let name = match &attr.name.prefix {
+                            Some(prefix) if !prefix.is_empty() => {
+                                format!("{}:{}", prefix, attr.name.local)
+                            }
+                            _ => attr.name.local.to_string(),
+                        };
+                        (name, attr.value.to_string())
+                    })
+                    .collect(),
+            },
+            NodeData::Text { contents } => SourceData::Text(contents.borrow().to_string()),
+            NodeData::Comment { contents } => SourceData::Comment(contents.to_string()),
+            NodeData::ProcessingInstruction { .. } | NodeData::Doctype { .. } => SourceData::Other,
+        }
+    }
 
-    convert_handle(&dom.document, false, false)
+    fn child_nodes(&self) -> Vec<Self> {
+        self.children.borrow().clone()
+    }
 }
 
-/// Converts an html5ever Handle to our Node structure
-/// Tracks context: whether we're inside a CODE element and/or PRE block
-fn convert_handle(handle: &Handle, in_code: bool, in_pre: bool) -> Node {
-    let node = handle.as_ref();
+#[cfg(feature = "simple-parser")]
+impl SourceNode for &RawNode {
+    fn data(&self) -> SourceData {
+        match self {
+            RawNode::Document(_) => SourceData::Document,
+            RawNode::Element {
+                name, attributes, ..
+            } => SourceData::Element {
+                name: name.clone(),
+                attributes: attributes.clone(),
+            },
+            RawNode::Text(text) => SourceData::Text(text.clone()),
+            RawNode::Comment(text) => SourceData::Comment(text.clone()),
+            RawNode::Doctype => SourceData::Other,
+        }
+    }
 
-    match &node.data {
-        NodeData::Document => {
+    fn child_nodes(&self) -> Vec<Self> {
+        match self {
+            RawNode::Document(children) | RawNode::Element { children, .. } => {
+                children.iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Converts a parsed node to our Node structure
+fn convert_node<N: SourceNode>(node: &N, context: ParseContext<'_>) -> Node {
+    match node.data() {
+        SourceData::Document => {
             let mut doc_node = Node::new_document();
-            for child in node.children.borrow().iter() {
-                doc_node.add_child(convert_handle(child, false, false));
+            for child in node.child_nodes() {
+                doc_node.add_child(convert_node(&child, context));
             }
+            drop_insignificant_whitespace(&mut doc_node, true, false);
             doc_node
         }
-        NodeData::Element { name, attrs, .. } => {
-            let tag_name = name.local.to_string();
-            let mut elem = Node::new_element(&tag_name);
+        SourceData::Element { name, attributes } => {
+            let mut elem = Node::new_element(&name);
 
-            // Copy attributes from html5ever
-            for attr in attrs.borrow().iter() {
-                elem.set_attribute(&attr.name.local.to_string(), &attr.value.to_string());
+            // Copy attributes from the parser
+            for (key, value) in &attributes {
+                elem.set_attribute(key, value);
             }
 
             // Update context for children
-            let is_pre = tag_name.eq_ignore_ascii_case("PRE") || in_pre;
-            let is_code = tag_name.eq_ignore_ascii_case("CODE") && !is_pre;
+            let is_raw = context
+                .raw_markdown_attribute
+                .map_or(false, |attribute| elem.get_attribute(attribute).is_some());
+            let is_pre = elem.is_preformatted() || is_raw || context.in_pre;
+            let is_code = name.eq_ignore_ascii_case("CODE") && !is_pre;
+            let child_context = ParseContext {
+                in_code: is_code || context.in_code,
+                in_pre: is_pre,
+                preserve_lines: context.preserve_lines
+                    || elem.get_attribute("style").map_or(false, |style| {
+                        parse_style(&style).get("white-space").map(String::as_str)
+                            == Some("pre-line")
+                    }),
+                in_foreign: context.in_foreign
+                    || name.eq_ignore_ascii_case("svg")
+                    || name.eq_ignore_ascii_case("math"),
+                depth: context.depth + 1,
+                ..context
+            };
+
+            // Beyond the maximum depth the subtree is kept as plain text, so
+            // adversarial nesting cannot exhaust the stack during conversion
+            if child_context.depth >= context.max_depth {
+                let text = subtree_text(node);
+                let text = if child_context.in_code || child_context.in_pre {
+                    text
+                } else {
+                    collapse_whitespace(&text)
+                };
+                let mut text_node = Node::new_text(&text);
+                text_node.is_code = child_context.in_code;
+                elem.add_child(text_node);
+                return elem;
+            }
 
             // Process children with updated context
-            for child in node.children.borrow().iter() {
-                elem.add_child(convert_handle(child, is_code || in_code, is_pre));
+            let keeps_lines =
+                child_context.preserve_lines && !child_context.in_pre && !child_context.in_code;
+            for child in node.child_nodes() {
+                if keeps_lines {
+                    if let SourceData::Text(text) = child.data() {
+                        elem.children.extend(line_break_nodes(&text));
+                        continue;
+                    }
+                }
+                elem.add_child(convert_node(&child, child_context));
+            }
+            if !child_context.in_pre && !child_context.in_code {
+                // Only the text elements of an SVG image render their whitespace
+                let in_markup = child_context.in_foreign
+                    && !["text", "tspan", "textPath"]
+                        .iter()
+                        .any(|text| name.eq_ignore_ascii_case(text));
+                let is_block = elem.is_block() || name.eq_ignore_ascii_case("head");
+                drop_insignificant_whitespace(&mut elem, is_block || in_markup, in_markup);
             }
 
             elem
         }
-        NodeData::Text { contents } => {
-            let text = contents.borrow().to_string();
+        SourceData::Text(text) => {
             // Only collapse whitespace if not in code/pre context
-            let processed = if in_code || in_pre {
+            let processed = if context.in_code || context.in_pre {
                 text
             } else {
-                crate::utilities::collapse_whitespace(&text)
+                collapse_whitespace(&text)
             };
             let mut text_node = Node::new_text(&processed);
-            text_node.is_code = in_code;
+            text_node.is_code = context.in_code;
             text_node
         }
-        NodeData::Comment { contents } => Node::new_comment(&contents.to_string()),
-        NodeData::ProcessingInstruction { .. } | NodeData::Doctype { .. } => Node::new_document(),
+        SourceData::Comment(contents) => Node::new_comment(&contents),
+        SourceData::Other => Node::new_document(),
+    }
+}
+
+/// Removes the collapsed whitespace-only text between the children of an element
+/// that does not render: next to a block or line break, at the edges of a block
+/// parent, or anywhere in the markup of an SVG or MathML image. Between inline
+/// content it is kept, as the space separating words
+fn drop_insignificant_whitespace(parent: &mut Node, is_block: bool, drop_all: bool) {
+    let is_space = |node: &Node| node.node_type == NodeType::Text && node.node_value == " ";
+    if !parent.children.iter().any(is_space) {
+        return;
+    }
+
+    let is_boundary = |node: Option<&Node>| {
+        node.map_or(is_block, |node| node.is_block() || node.node_name == "BR")
+    };
+    let keep: Vec<bool> = (0..parent.children.len())
+        .map(|index| {
+            if !is_space(&parent.children[index]) {
+                return true;
+            }
+            let is_content = |node: &&Node| node.node_type != NodeType::Comment;
+            let before = parent.children[..index].iter().rev().find(is_content);
+            let after = parent.children[index + 1..].iter().find(is_content);
+            !drop_all && !is_boundary(before) && !is_boundary(after)
+        })
+        .collect();
+
+    let mut keep = keep.into_iter();
+    parent.children.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// Collects the text of a subtree without recursion
+fn subtree_text<N: SourceNode>(node: &N) -> String {
+    let mut text = String::new();
+    let mut stack = node.child_nodes();
+    stack.reverse();
+    while let Some(node) = stack.pop() {
+        if let SourceData::Text(contents) = node.data() {
+            text.push_str(&contents);
+        }
+        stack.extend(node.child_nodes().into_iter().rev());
     }
+    text
+}
+
+/// Collapses text while turning the newlines inside it into BR elements,
+/// so separate source lines render as separate lines
+fn line_break_nodes(text: &str) -> Vec<Node> {
+    let is_line_space = |c: char| matches!(c, ' ' | '\t' | '\r' | '\n');
+    let start = text.len() - text.trim_start_matches(is_line_space).len();
+    let end = text.trim_end_matches(is_line_space).len();
+    if start >= end {
+        return vec![Node::new_text(&collapse_whitespace(text))];
+    }
+
+    let lines: Vec<&str> = text[start..end]
+        .split('\n')
+        .map(|line| line.trim_matches(is_line_space))
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut nodes = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 {
+            nodes.push(Node::new_element("br"));
+        }
+        let leading = if index == 0 { &text[..start] } else { "" };
+        let trailing = if index == lines.len() - 1 {
+            &text[end..]
+        } else {
+            ""
+        };
+        let line = format!("{}{}{}", leading, line, trailing);
+        nodes.push(Node::new_text(&collapse_whitespace(&line)));
+    }
+    nodes
 }
 
 #[cfg(test)]
@@ -147,4 +397,100 @@ mod tests {
         let is_marked = find_code_is_marked(&doc);
         assert_eq!(is_marked, Some(false));
     }
+
+    #[test]
+    fn test_preserve_line_breaks() {
+        let html = "<p>\n  Roses are red,\n  violets are blue\n</p>";
+        let options = TurndownOptions {
+            whitespace_mode: WhitespaceMode::PreserveLineBreaks,
+            ..Default::default()
+        };
+        let doc = parse_html_with_options(html, &options);
+
+        fn find_paragraph(node: &Node) -> Option<&Node> {
+            if node.node_name == "P" {
+                return Some(node);
+            }
+            node.children.iter().find_map(find_paragraph)
+        }
+
+        let names: Vec<&str> = find_paragraph(&doc)
+            .unwrap()
+            .children
+            .iter()
+            .map(|child| child.node_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["#text", "BR", "#text"]);
+    }
+
+    #[test]
+    fn test_max_depth_flattens_subtree() {
+        let html = format!(
+            "{}deep <b>text</b>{}",
+            "<div>".repeat(50),
+            "</div>".repeat(50)
+        );
+        let options = TurndownOptions {
+            max_depth: 10,
+            ..Default::default()
+        };
+        let doc = parse_html_with_options(&html, &options);
+
+        // Ten levels of elements with the flattened text below them
+        assert_eq!(doc.depth(), 11);
+        assert_eq!(doc.text_content(), "deep text");
+    }
+
+    #[test]
+    fn test_entities_decode_into_text() {
+        let doc = parse_html("<p>Wrap it in &lt;div&gt;&amp;lt;/div&gt;</p>");
+        let body = &doc.children[0].children[1];
+        let paragraph = &body.children[0];
+
+        assert_eq!(paragraph.children.len(), 1);
+        assert_eq!(paragraph.children[0].node_type, NodeType::Text);
+        assert_eq!(
+            paragraph.children[0].node_value,
+            "Wrap it in <div>&lt;/div>"
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "html5ever", feature = "simple-parser"))]
+    fn test_simple_parser_matches_html5ever() {
+        let inputs = [
+            "<p>Hello <strong>World</strong></p>",
+            "Plain text &amp; entities&nbsp;&#8212; &lt;here&gt;",
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Hi &amp; bye</title>\n<style>p > a { color: red; }</style>\n</head>\n<body class=\"mail\">\n<h1>Title</h1>\n<p>Text</p>\n</body>\n</html>\n",
+            "<!-- preheader --><div><p>One</p><p>Two<br>lines</p></div>",
+            "<ul>\n  <li>First</li>\n  <li>Second <a href=\"https://example.com/?a=1&amp;b=2\" title='t'>link</a></li>\n</ul>",
+            "<ol start=\"3\"><li>Three<li>Four</ol>",
+            "<pre>\n  indented\n    code &lt;b&gt;\n</pre>",
+            "<p>Inline <code>x &lt; y</code> and <img src=\"a.png\" alt=\"A\"> image</p>",
+            "<table><tr><th>Name</th><th>Value</th></tr><tr><td>a</td><td>1</td></tr></table>",
+            "<table>\n<thead><tr><th>H</th></tr></thead>\n<tbody><tr><td>D</td></tr></tbody>\n</table>",
+            "<blockquote><p>Quote</p><footer>Someone</footer></blockquote><hr><p>After</p>",
+            "<table>\n  <tr><td>Widgets</td><td>12</td></tr>\n  <caption>Sales</caption>\n</table>",
+            "<dl><dt>Term</dt><dd>Definition</dd></dl>",
+            "<p>Para<div>Block</div>",
+            "<script>if (a < b) { run(); }</script><p>Visible</p>",
+            "<p>Icon <svg viewBox=\"0 0 10 10\" xmlns:xlink=\"http://www.w3.org/1999/xlink\"><linearGradient id=\"g\"/><use xlink:href=\"#g\"/></svg> here</p>",
+            "<p>Caf\u{e9} \u{2014} \u{1f600}</p>\r\n<p>Next</p>",
+            "<p>x <math><mi><![CDATA[y < z]]></mi></math> <![CDATA[bogus]]> w</p>",
+        ];
+
+        let html5ever = TurndownOptions::default();
+        let simple = TurndownOptions {
+            parser: ParserKind::Simple,
+            ..Default::default()
+        };
+        for html in inputs {
+            assert_eq!(
+                parse_html_with_options(html, &simple),
+                parse_html_with_options(html, &html5ever),
+                "parsers differ on {:?}",
+                html
+            );
+        }
+    }
 }
@@ -0,0 +1,277 @@
+//! Collection of reference-style links and their definitions
+//!
+//! Rules convert one node at a time, so the reference link rule writes each
+//! link as a marked placeholder holding its text, destination and title. Once the
+//! whole document is converted, the placeholders are numbered or labelled in
+//! order of appearance and the definitions are appended.
+
+use crate::commonmark_rules::{link_destination, link_title};
+use crate::{LinkReferenceStyle, ReferenceLabels, ReferenceOrder, TurndownOptions};
+use std::collections::HashMap;
+
+/// Opens a reference link placeholder, followed by the link text
+const LINK_START: char = '\u{FDD1}';
+/// Separates the link text from the destination
+const LINK_DESTINATION: char = '\u{FDD2}';
+/// Separates the destination from the title
+const LINK_TITLE: char = '\u{FDD4}';
+/// Closes a reference link placeholder
+const LINK_END: char = '\u{FDD3}';
+
+/// Writes the placeholder for a reference link, resolved by [`resolve`]
+pub(crate) fn placeholder(content: &str, href: &str, title: &str) -> String {
+    format!(
+        "{}{}{}{}{}{}{}",
+        LINK_START, content, LINK_DESTINATION, href, LINK_TITLE, title, LINK_END
+    )
+}
+
+/// A link definition collected from the placeholders
+struct Definition {
+    label: String,
+    href: String,
+    title: String,
+}
+
+/// Replaces reference link placeholders with links in the configured style and
+/// appends their definitions to the end of the Markdown
+pub(crate) fn resolve(markdown: &str, options: &TurndownOptions) -> String {
+    if !markdown.contains(LINK_END) {
+        return markdown.to_string();
+    }
+
+    let mut definitions: Vec<Definition> = Vec::new();
+    let mut by_target: HashMap<(String, String), usize> = HashMap::new();
+
+    let output = replace_placeholders(markdown, |content, href, title| {
+        let (content, href, title) = (content.to_string(), href.to_string(), title.to_string());
+        match options.link_reference_style {
+            LinkReferenceStyle::Collapsed => {
+                match text_label(&mut definitions, &content, &href, &title) {
+                    Some(label) => format!("[{}][{}]", content, label),
+                    None => format!("[{}][]", content),
+                }
+            }
+            LinkReferenceStyle::Shortcut => {
+                match text_label(&mut definitions, &content, &href, &title) {
+                    Some(label) => format!("[{}][{}]", content, label),
+                    None => format!("[{}]", content),
+                }
+            }
+            LinkReferenceStyle::Full => {
+                // Links to the same URL with different titles need their own definitions
+                let key = (href.clone(), title.clone());
+                let index = *by_target.entry(key).or_insert_with(|| {
+                    let label = match options.link_reference_format.labels {
+                        ReferenceLabels::Numeric => (definitions.len() + 1).to_string(),
+                        ReferenceLabels::Slug => unique_slug(&definitions, &content),
+                    };
+                    definitions.push(Definition {
+                        label,
+                        href: href.clone(),
+                        title: title.clone(),
+                    });
+                    definitions.len() - 1
+                });
+                format!("[{}][{}]", content, definitions[index].label)
+            }
+        }
+    });
+
+    let lines = definition_lines(definitions, &options.link_reference_format.order);
+    format!("{}\n\n{}", output, lines)
+}
+
+/// Replaces reference link placeholders with inline links, for Markdown that is
+/// not a whole document and so has no end to append definitions to
+pub(crate) fn inline(markdown: &str) -> String {
+    replace_placeholders(markdown, |content, href, title| {
+        format!(
+            "[{}]({}{})",
+            content,
+            link_destination(href),
+            link_title(title)
+        )
+    })
+}
+
+/// Replaces each placeholder with the link written for its text, destination
+/// and title. Links nested in link text close first, so the innermost is
+/// replaced first
+fn replace_placeholders(
+    markdown: &str,
+    mut link: impl FnMut(&str, &str, &str) -> String,
+) -> String {
+    let mut output = markdown.to_string();
+    while let Some(end) = output.find(LINK_END) {
+        let Some(start) = output[..end].rfind(LINK_START) else {
+            output.replace_range(end..end + LINK_END.len_utf8(), "");
+            continue;
+        };
+        let body = &output[start + LINK_START.len_utf8()..end];
+        let (content, target) = body.split_once(LINK_DESTINATION).unwrap_or((body, ""));
+        let (href, title) = target.split_once(LINK_TITLE).unwrap_or((target, ""));
+        let replacement = link(content, href, title);
+        output.replace_range(start..end + LINK_END.len_utf8(), &replacement);
+    }
+    output
+}
+
+/// Adds the definition of a link labelled by its own text, unless one exists.
+/// Labels match case-insensitively, so when the text already labels another
+/// target, the link gets the text with a numeric suffix as its label instead,
+/// which is returned
+fn text_label(
+    definitions: &mut Vec<Definition>,
+    content: &str,
+    href: &str,
+    title: &str,
+) -> Option<String> {
+    let bound = |definitions: &[Definition], label: &str| {
+        let label = label.to_lowercase();
+        definitions
+            .iter()
+            .find(|definition| definition.label.to_lowercase() == label)
+            .map(|definition| definition.href == href && definition.title == title)
+    };
+
+    let label = match bound(definitions, content) {
+        Some(true) => return None,
+        None => None,
+        Some(false) => (2..)
+            .map(|suffix| format!("{}-{}", content, suffix))
+            .find(|label| bound(definitions, label) != Some(false)),
+    };
+    let name = label.as_deref().unwrap_or(content);
+    if bound(definitions, name).is_none() {
+        definitions.push(Definition {
+            label: name.to_string(),
+            href: href.to_string(),
+            title: title.to_string(),
+        });
+    }
+    label
+}
+
+/// Derives a label from link text, made of its lowercase letters and digits
+/// joined by dashes, with a numeric suffix when an earlier link took it
+fn unique_slug(definitions: &[Definition], content: &str) -> String {
+    let slug = content
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-");
+    let slug = if slug.is_empty() {
+        "link".to_string()
+    } else {
+        slug
+    };
+
+    let is_taken = |label: &str| {
+        definitions
+            .iter()
+            .any(|definition| definition.label == label)
+    };
+    if !is_taken(&slug) {
+        return slug;
+    }
+    (2..)
+        .map(|suffix| format!("{}-{}", slug, suffix))
+        .find(|label| !is_taken(label))
+        .unwrap_or(slug)
+}
+
+/// Formats the definitions in the configured order, one per line
+fn definition_lines(mut definitions: Vec<Definition>, order: &ReferenceOrder) -> String {
+    let line = |definition: &Definition| {
+        format!(
+            "[{}]: {}{}",
+            definition.label,
+            link_destination(&definition.href),
+            link_title(&definition.title)
+        )
+    };
+
+    match order {
+        ReferenceOrder::FirstAppearance => {}
+        ReferenceOrder::ByUrl => definitions.sort_by(|a, b| a.href.cmp(&b.href)),
+        ReferenceOrder::GroupedByHost => {
+            // Hosts keep the order they first appear in, each group separated by a blank line
+            let mut groups: Vec<(&str, Vec<String>)> = Vec::new();
+            for definition in &definitions {
+                let host = url_host(&definition.href);
+                match groups.iter_mut().find(|(name, _)| *name == host) {
+                    Some((_, lines)) => lines.push(line(definition)),
+                    None => groups.push((host, vec![line(definition)])),
+                }
+            }
+            return groups
+                .into_iter()
+                .map(|(_, lines)| lines.join("\n"))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+        }
+    }
+
+    definitions.iter().map(line).collect::<Vec<_>>().join("\n")
+}
+
+/// Gets the host of a URL, or its scheme for URLs without an authority such as `mailto:`
+fn url_host(url: &str) -> &str {
+    match url.split_once("://") {
+        Some((_, rest)) => rest.split(['/', '?', '#']).next().unwrap_or(rest),
+        None => url.split(':').next().unwrap_or(url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_numbers_links_once_per_url() {
+        let markdown = format!(
+            "{} and {} or {}",
+            placeholder("One", "https://a.test/", ""),
+            placeholder("Two", "https://b.test/", ""),
+            placeholder("Again", "https://a.test/", "")
+        );
+        let options = TurndownOptions::default();
+
+        assert_eq!(
+            resolve(&markdown, &options),
+            "[One][1] and [Two][2] or [Again][1]\n\n[1]: https://a.test/\n[2]: https://b.test/"
+        );
+    }
+
+    #[test]
+    fn test_resolve_gives_text_labels_of_other_targets_a_suffix() {
+        let markdown = format!(
+            "{} and {} or {} and {}",
+            placeholder("Docs", "https://a.test/", ""),
+            placeholder("docs", "https://b.test/", ""),
+            placeholder("DOCS", "https://b.test/", ""),
+            placeholder("docs", "https://a.test/", "")
+        );
+        let options = TurndownOptions {
+            link_reference_style: LinkReferenceStyle::Collapsed,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            resolve(&markdown, &options),
+            "[Docs][] and [docs][docs-2] or [DOCS][DOCS-2] and [docs][]\n\n\
+             [Docs]: https://a.test/\n[docs-2]: https://b.test/"
+        );
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("https://www.example.com/path?q"),
+            "www.example.com"
+        );
+        assert_eq!(url_host("mailto:someone@example.com"), "mailto");
+    }
+}
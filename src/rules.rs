@@ -1,5 +1,6 @@
 use crate::node::Node;
 use crate::TurndownOptions;
+use crate::{commonmark_rules, gfm_rules};
 
 /// A replacement function for converting HTML to Markdown
 pub type ReplacementFn = fn(&str, &Node, &TurndownOptions) -> String;
@@ -45,11 +46,46 @@ impl RuleFilter {
     }
 }
 
+/// Gets the keys of the built-in rules in the order they are checked
+///
+/// GFM rules refine the CommonMark ones, so they are checked after the rules
+/// that drop content or handle email list tables but before all others.
+fn rule_order() -> Vec<&'static str> {
+    let mut order: Vec<&str> = commonmark_rules::ordered_rules()
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    let gfm = gfm_rules::ordered_rules().into_iter().map(|(key, _)| key);
+    let at = order
+        .iter()
+        .position(|key| *key == "paragraph")
+        .unwrap_or(order.len());
+    order.splice(at..at, gfm);
+    order
+}
+
+/// Gets the fallback rule, which passes inline content through and separates
+/// block content from its surroundings
+fn default_rule() -> Rule {
+    Rule {
+        filter: RuleFilter::String("default".to_string()),
+        replacement: |content, node, _| {
+            if node.is_block() {
+                format!("\n\n{}\n\n", content)
+            } else {
+                content.to_string()
+            }
+        },
+    }
+}
+
 /// Manages a collection of conversion rules
 pub struct Rules {
     pub array: Vec<Rule>,
     pub keep: Vec<Rule>,
     pub remove: Vec<Rule>,
+    /// Rule used for nodes that no other rule matches
+    pub default: Rule,
     pub options: TurndownOptions,
 }
 
@@ -60,22 +96,40 @@ impl Rules {
             array: Vec::new(),
             keep: Vec::new(),
             remove: Vec::new(),
+            default: default_rule(),
             options: options.clone(),
         };
 
-        // Initialize with default rules from options
-        for (_, rule) in &options.rules {
-            rules.array.push(rule.clone());
-        }
+        // Initialize with the rules from options, built-in rules in their defined
+        // order followed by any others sorted by key
+        let order = rule_order();
+        let mut entries: Vec<(&String, &Rule)> = options.rules.iter().collect();
+        entries.sort_by_key(|(key, _)| {
+            let position = order.iter().position(|name| name == key);
+            (position.unwrap_or(order.len()), key.to_string())
+        });
+        rules.array = entries.into_iter().map(|(_, rule)| rule.clone()).collect();
 
         rules
     }
 
-    /// Adds a new rule to the beginning of the rules list
+    /// Adds a new rule to the beginning of the rules list, giving it the highest precedence
     pub fn add(&mut self, _key: String, rule: Rule) {
         self.array.insert(0, rule);
     }
 
+    /// Adds several rules to the beginning of the rules list, keeping their order
+    /// so that the first given rule has the highest precedence
+    pub fn add_all(&mut self, rules: Vec<(String, Rule)>) {
+        self.array
+            .splice(0..0, rules.into_iter().map(|(_, rule)| rule));
+    }
+
+    /// Replaces the rule used for nodes that no other rule matches
+    pub fn set_default(&mut self, rule: Rule) {
+        self.default = rule;
+    }
+
     /// Marks a filter to keep nodes as HTML
     pub fn keep(&mut self, filter: RuleFilter) {
         self.keep.push(Rule {
@@ -94,12 +148,12 @@ impl Rules {
 
     /// Gets the appropriate rule for a node
     pub fn for_node(&self, node: &Node) -> Rule {
-        // Check if node is blank
-        if node.is_blank() {
+        // Check if node is blank, unless it is written as raw Markdown
+        if node.is_blank() && commonmark_rules::raw_markdown(node, &self.options).is_none() {
             return Rule {
                 filter: RuleFilter::String("blank".to_string()),
                 replacement: |_, node, _| {
-                    if node.is_block() {
+                    if node.is_block() && !commonmark_rules::is_document_wrapper(node) {
                         "\n\n".to_string()
                     } else {
                         String::new()
@@ -123,17 +177,7 @@ impl Rules {
             return rule.clone();
         }
 
-        // Return default rule
-        Rule {
-            filter: RuleFilter::String("default".to_string()),
-            replacement: |content, node, _| {
-                if node.is_block() {
-                    format!("\n\n{}\n\n", content)
-                } else {
-                    content.to_string()
-                }
-            },
-        }
+        self.default.clone()
     }
 
     /// Finds a rule that matches a node
@@ -165,6 +209,21 @@ mod tests {
         assert!(filter.matches(&node, &TurndownOptions::default()));
     }
 
+    #[test]
+    fn test_rules_follow_defined_order() {
+        let first = |rules: &Rules| {
+            let mut node = Node::new_element("td");
+            node.set_attribute("class", "list-item-prefix");
+            (rules.for_node(&node).replacement)("•", &node, &rules.options)
+        };
+
+        for _ in 0..20 {
+            let rules = Rules::new(TurndownOptions::default());
+            assert_eq!(first(&rules), "");
+            assert_eq!(rules.array.len(), commonmark_rules::ordered_rules().len());
+        }
+    }
+
     #[test]
     fn test_rule_filter_array() {
         let filter = RuleFilter::Array(vec!["p".to_string(), "div".to_string()]);
@@ -0,0 +1,814 @@
+use std::collections::HashMap;
+use std::mem;
+
+/// A node of the document tree built by the simple parser, before any
+/// whitespace or context handling
+#[derive(Debug)]
+pub enum RawNode {
+    Document(Vec<RawNode>),
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<RawNode>,
+    },
+    Text(String),
+    Comment(String),
+    Doctype,
+}
+
+impl RawNode {
+    fn element(name: &str, attributes: Vec<(String, String)>) -> Self {
+        RawNode::Element {
+            name: name.to_string(),
+            attributes,
+            children: Vec::new(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            RawNode::Element { name, .. } => name,
+            _ => "",
+        }
+    }
+
+    fn children_mut(&mut self) -> Option<&mut Vec<RawNode>> {
+        match self {
+            RawNode::Document(children) | RawNode::Element { children, .. } => Some(children),
+            _ => None,
+        }
+    }
+
+    /// Appends text, merging it into a preceding text node like html5ever does
+    fn push_text(&mut self, text: &str) {
+        if let Some(children) = self.children_mut() {
+            match children.last_mut() {
+                Some(RawNode::Text(last)) => last.push_str(text),
+                _ => children.push(RawNode::Text(text.to_string())),
+            }
+        }
+    }
+
+    fn push(&mut self, node: RawNode) {
+        if let Some(children) = self.children_mut() {
+            children.push(node);
+        }
+    }
+}
+
+impl Drop for RawNode {
+    // Deeply nested documents would overflow the stack with the derived drop
+    fn drop(&mut self) {
+        let mut stack = self.children_mut().map(mem::take).unwrap_or_default();
+        while let Some(mut node) = stack.pop() {
+            if let Some(children) = node.children_mut() {
+                stack.append(children);
+            }
+        }
+    }
+}
+
+/// Elements whose content is text up to the matching end tag
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "script", "style", "xmp", "iframe", "noembed", "noframes", "noscript",
+];
+
+/// Elements whose content is text with character references, up to the matching end tag
+const RCDATA_ELEMENTS: &[&str] = &["title", "textarea"];
+
+/// Elements that belong in the document head
+const HEAD_ELEMENTS: &[&str] = &[
+    "base", "basefont", "bgsound", "link", "meta", "title", "noscript", "noframes", "style",
+    "script", "template",
+];
+
+/// Elements that close an open paragraph when they start
+const CLOSES_PARAGRAPH: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+/// Elements an implied end tag search does not look past
+const SCOPE_BOUNDARIES: &[&str] = &[
+    "applet", "button", "caption", "html", "marquee", "object", "table", "td", "template", "th",
+];
+
+/// SVG attributes with mixed-case names, restored in SVG content like html5ever does
+const SVG_MIXED_CASE_ATTRIBUTES: &[&str] = &[
+    "attributeName",
+    "attributeType",
+    "baseFrequency",
+    "baseProfile",
+    "calcMode",
+    "clipPathUnits",
+    "diffuseConstant",
+    "edgeMode",
+    "filterUnits",
+    "glyphRef",
+    "gradientTransform",
+    "gradientUnits",
+    "kernelMatrix",
+    "kernelUnitLength",
+    "keyPoints",
+    "keySplines",
+    "keyTimes",
+    "lengthAdjust",
+    "limitingConeAngle",
+    "markerHeight",
+    "markerUnits",
+    "markerWidth",
+    "maskContentUnits",
+    "maskUnits",
+    "numOctaves",
+    "pathLength",
+    "patternContentUnits",
+    "patternTransform",
+    "patternUnits",
+    "pointsAtX",
+    "pointsAtY",
+    "pointsAtZ",
+    "preserveAlpha",
+    "preserveAspectRatio",
+    "primitiveUnits",
+    "refX",
+    "refY",
+    "repeatCount",
+    "repeatDur",
+    "requiredExtensions",
+    "requiredFeatures",
+    "specularConstant",
+    "specularExponent",
+    "spreadMethod",
+    "startOffset",
+    "stdDeviation",
+    "stitchTiles",
+    "surfaceScale",
+    "systemLanguage",
+    "tableValues",
+    "targetX",
+    "targetY",
+    "textLength",
+    "viewBox",
+    "viewTarget",
+    "xChannelSelector",
+    "yChannelSelector",
+    "zoomAndPan",
+];
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "keygen", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+/// Parses well-formed HTML into the document shape html5ever builds: an HTML
+/// element holding HEAD and BODY, with TBODY around bare table rows. Malformed
+/// markup is handled leniently, without the error recovery of the HTML5 algorithm.
+pub fn parse(html: &str) -> RawNode {
+    // Line endings are normalized before tokenizing, as in html5ever
+    let html = html.replace("\r\n", "\n").replace('\r', "\n");
+    let mut builder = TreeBuilder::default();
+    let mut rest = html.as_str();
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after.find("-->").unwrap_or(after.len());
+            builder.comment(&after[..end]);
+            rest = after.get(end + 3..).unwrap_or("");
+        } else if let Some(after) = rest
+            .strip_prefix("<![CDATA[")
+            .filter(|_| builder.is_open("svg") || builder.is_open("math"))
+        {
+            // CDATA sections are text in SVG and MathML, and bogus comments elsewhere
+            let end = after.find("]]>").unwrap_or(after.len());
+            builder.text(&after[..end]);
+            rest = after.get(end + 3..).unwrap_or("");
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            // Doctypes, and everything else html5ever reads as a bogus comment
+            let end = rest.find('>').unwrap_or(rest.len());
+            let is_doctype = rest
+                .get(2..9)
+                .map_or(false, |keyword| keyword.eq_ignore_ascii_case("doctype"));
+            if is_doctype {
+                builder.doctype();
+            } else {
+                let start = if rest.starts_with("<?") { 1 } else { 2 };
+                builder.comment(&rest[start..end]);
+            }
+            rest = rest.get(end + 1..).unwrap_or("");
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').unwrap_or(after.len());
+            let name = tag_name(&after[..end]);
+            if !name.is_empty() {
+                builder.end_tag(&name);
+            }
+            rest = after.get(end + 1..).unwrap_or("");
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            let (name, attributes, self_closing, after) = parse_start_tag(&rest[1..]);
+            rest = after;
+
+            let is_raw = RAW_TEXT_ELEMENTS.contains(&name.as_str());
+            if is_raw || RCDATA_ELEMENTS.contains(&name.as_str()) {
+                builder.start_tag(&name, attributes, false);
+                let end = find_end_tag(rest, &name);
+                if is_raw {
+                    builder.text(&rest[..end]);
+                } else {
+                    builder.text(&decode_entities(&rest[..end]));
+                }
+                builder.end_tag(&name);
+                rest = &rest[end..];
+                rest = rest.find('>').map_or("", |close| &rest[close + 1..]);
+            } else {
+                builder.start_tag(&name, attributes, self_closing);
+            }
+        } else {
+            let skip = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[skip..]
+                .find('<')
+                .map_or(rest.len(), |index| index + skip);
+            builder.text(&decode_entities(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+
+    builder.finish()
+}
+
+/// Reads the lowercase tag name at the start of a tag
+fn tag_name(tag: &str) -> String {
+    tag.split(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Parses a start tag after its `<`, returning the name, attributes,
+/// whether it is self-closing and the input following the tag
+fn parse_start_tag(input: &str) -> (String, Vec<(String, String)>, bool, &str) {
+    let name = tag_name(input);
+    let mut rest = &input[name.len()..];
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    let mut self_closing = false;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            rest = after;
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            self_closing = after.starts_with('>');
+            rest = after;
+            continue;
+        }
+        self_closing = false;
+
+        let skip = rest.chars().next().map_or(1, char::len_utf8);
+        let name_end = rest[skip..]
+            .find(|c: char| c.is_ascii_whitespace() || matches!(c, '=' | '>' | '/'))
+            .map_or(rest.len(), |index| index + skip);
+        let attribute = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            rest = after.trim_start_matches(|c: char| c.is_ascii_whitespace());
+            let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''));
+            let (raw, after) = match quote {
+                Some(quote) => {
+                    let end = rest[1..].find(quote).map_or(rest.len(), |index| index + 1);
+                    (&rest[1..end], rest.get(end + 1..).unwrap_or(""))
+                }
+                None => {
+                    let end = rest
+                        .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                        .unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+            value = decode_entities(raw);
+            rest = after;
+        }
+
+        // Like html5ever, the first of several attributes with the same name wins
+        if !attributes.iter().any(|(name, _)| *name == attribute) {
+            attributes.push((attribute, value));
+        }
+    }
+
+    (name, attributes, self_closing, rest)
+}
+
+/// Finds the start of the end tag closing a raw text element
+fn find_end_tag(input: &str, name: &str) -> usize {
+    let bytes = input.as_bytes();
+    let mut index = 0;
+    while let Some(offset) = input[index..].find("</") {
+        let start = index + offset;
+        let tag = &bytes[start + 2..];
+        let matches_name = tag.len() >= name.len()
+            && tag[..name.len()].eq_ignore_ascii_case(name.as_bytes())
+            && tag.get(name.len()).map_or(true, |c| {
+                c.is_ascii_whitespace() || matches!(c, b'/' | b'>')
+            });
+        if matches_name {
+            return start;
+        }
+        index = start + 2;
+    }
+    input.len()
+}
+
+/// Decodes numeric and the common named character references
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 32)
+            .and_then(|end| {
+                let reference = &rest[1..end + 1];
+                decode_reference(reference).map(|c| (c, end + 2))
+            });
+        match decoded {
+            Some((c, length)) => {
+                result.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Decodes the name of a character reference between `&` and `;`
+fn decode_reference(reference: &str) -> Option<char> {
+    if let Some(number) = reference.strip_prefix('#') {
+        let code = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return Some(
+            char::from_u32(code)
+                .filter(|c| *c != '\0')
+                .unwrap_or('\u{FFFD}'),
+        );
+    }
+
+    let c = match reference {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{A0}',
+        "shy" => '\u{AD}',
+        "ensp" => '\u{2002}',
+        "emsp" => '\u{2003}',
+        "thinsp" => '\u{2009}',
+        "zwnj" => '\u{200C}',
+        "zwj" => '\u{200D}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "hellip" => '…',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "sect" => '§',
+        "para" => '¶',
+        "times" => '×',
+        "divide" => '÷',
+        "cent" => '¢',
+        "pound" => '£',
+        "yen" => '¥',
+        "euro" => '€',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Restores the name of an SVG attribute as html5ever reports it: mixed case
+/// names get their case back, and namespaced names such as xlink:href keep
+/// their prefix
+fn adjust_svg_attribute((name, value): (String, String)) -> (String, String) {
+    let name = SVG_MIXED_CASE_ATTRIBUTES
+        .iter()
+        .find(|attribute| attribute.eq_ignore_ascii_case(&name))
+        .map_or(name, |attribute| attribute.to_string());
+    (name, value)
+}
+
+/// Where the builder is in the document, following the HTML5 insertion modes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Mode {
+    #[default]
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+    AfterBody,
+    AfterAfterBody,
+}
+
+/// Builds the document from tokens, implying the HTML, HEAD and BODY elements
+#[derive(Default)]
+struct TreeBuilder {
+    document: Vec<RawNode>,
+    /// Open elements, starting with the HTML element
+    stack: Vec<RawNode>,
+    /// Number of open elements by name, so lookups need not walk a deep stack
+    open_counts: HashMap<String, usize>,
+    mode: Mode,
+    /// The next newline is dropped, as the first newline of a PRE block is
+    skip_newline: bool,
+}
+
+impl TreeBuilder {
+    fn doctype(&mut self) {
+        if self.mode == Mode::BeforeHtml && self.stack.is_empty() {
+            self.document.push(RawNode::Doctype);
+        }
+    }
+
+    fn comment(&mut self, text: &str) {
+        let comment = RawNode::Comment(text.to_string());
+        match self.mode {
+            Mode::BeforeHtml | Mode::AfterAfterBody => self.document.push(comment),
+            Mode::AfterBody => self.stack[0].push(comment),
+            _ => self.current().push(comment),
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let mut text = text;
+        if mem::take(&mut self.skip_newline) {
+            text = text.strip_prefix('\n').unwrap_or(text);
+        }
+        if text.is_empty() {
+            return;
+        }
+
+        let is_space = |c: char| matches!(c, ' ' | '\t' | '\n' | '\x0C' | '\r');
+        let content = text.trim_start_matches(is_space);
+        let space = &text[..text.len() - content.len()];
+
+        match self.mode {
+            Mode::BeforeHtml | Mode::BeforeHead => {
+                if !content.is_empty() {
+                    self.open_body();
+                    self.current().push_text(content);
+                }
+            }
+            // Raw text of a head element, such as a title or style
+            Mode::InHead
+                if self
+                    .stack
+                    .last()
+                    .map_or(false, |node| node.name() != "head") =>
+            {
+                self.current().push_text(text)
+            }
+            Mode::InHead | Mode::AfterHead => {
+                if !space.is_empty() {
+                    self.current().push_text(space);
+                }
+                if !content.is_empty() {
+                    self.open_body();
+                    self.current().push_text(content);
+                }
+            }
+            Mode::InBody | Mode::AfterBody | Mode::AfterAfterBody => {
+                if !content.is_empty() {
+                    self.mode = Mode::InBody;
+                }
+                self.current().push_text(text);
+            }
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, attributes: Vec<(String, String)>, self_closing: bool) {
+        match (self.mode, name) {
+            (Mode::BeforeHtml, "html") => {
+                self.open(RawNode::element("html", attributes));
+                self.mode = Mode::BeforeHead;
+                return;
+            }
+            (_, "html") | (Mode::InBody, "body") => {
+                let index = if name == "html" { 0 } else { 1 };
+                if let Some(RawNode::Element {
+                    attributes: existing,
+                    ..
+                }) = self.stack.get_mut(index)
+                {
+                    for (key, value) in attributes {
+                        if !existing.iter().any(|(name, _)| *name == key) {
+                            existing.push((key, value));
+                        }
+                    }
+                }
+                return;
+            }
+            (Mode::BeforeHtml | Mode::BeforeHead, "head") => {
+                self.open_html();
+                self.open(RawNode::element("head", attributes));
+                self.mode = Mode::InHead;
+                return;
+            }
+            (Mode::BeforeHtml | Mode::BeforeHead | Mode::InHead, _)
+                if HEAD_ELEMENTS.contains(&name) =>
+            {
+                self.open_head();
+            }
+            (Mode::BeforeHtml | Mode::BeforeHead | Mode::InHead | Mode::AfterHead, "body") => {
+                self.close_head();
+                self.open(RawNode::element("body", attributes));
+                self.mode = Mode::InBody;
+                return;
+            }
+            (Mode::InBody, _) => {}
+            _ => {
+                self.open_body();
+                self.mode = Mode::InBody;
+            }
+        }
+
+        self.imply_end_tags(name);
+        self.imply_table_sections(name);
+
+        let is_foreign = self.is_open("svg") || self.is_open("math");
+        let attributes = if is_foreign || name == "svg" {
+            attributes.into_iter().map(adjust_svg_attribute).collect()
+        } else {
+            attributes
+        };
+        let element = RawNode::element(name, attributes);
+        if VOID_ELEMENTS.contains(&name)
+            || (self_closing && (is_foreign || matches!(name, "svg" | "math")))
+        {
+            self.current().push(element);
+        } else {
+            self.open(element);
+            self.skip_newline = matches!(name, "pre" | "listing" | "textarea");
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        self.skip_newline = false;
+        match (self.mode, name) {
+            (Mode::InHead, "head") => {
+                self.close_head();
+            }
+            (Mode::InBody, "body") => self.mode = Mode::AfterBody,
+            (Mode::InBody | Mode::AfterBody, "html") => self.mode = Mode::AfterAfterBody,
+            (Mode::InBody, "br") => self.start_tag("br", Vec::new(), false),
+            (Mode::InHead, _) if HEAD_ELEMENTS.contains(&name) => self.close(name),
+            (Mode::InBody, _) => self.close(name),
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> RawNode {
+        self.open_body();
+        while self.stack.len() > 1 {
+            self.pop();
+        }
+        if let Some(html) = self.stack.pop() {
+            self.document.push(html);
+        }
+        RawNode::Document(mem::take(&mut self.document))
+    }
+
+    fn current(&mut self) -> &mut RawNode {
+        self.open_html();
+        self.stack.last_mut().unwrap()
+    }
+
+    fn open(&mut self, element: RawNode) {
+        *self
+            .open_counts
+            .entry(element.name().to_string())
+            .or_default() += 1;
+        self.stack.push(element);
+    }
+
+    fn pop(&mut self) {
+        if let Some(node) = self.stack.pop() {
+            if let Some(count) = self.open_counts.get_mut(node.name()) {
+                *count -= 1;
+            }
+            self.current().push(node);
+        }
+    }
+
+    fn is_open(&self, name: &str) -> bool {
+        self.open_counts.get(name).map_or(false, |count| *count > 0)
+    }
+
+    fn open_html(&mut self) {
+        if self.stack.is_empty() {
+            self.open(RawNode::element("html", Vec::new()));
+            self.mode = Mode::BeforeHead;
+        }
+    }
+
+    fn open_head(&mut self) {
+        self.open_html();
+        if self.mode == Mode::BeforeHead {
+            self.open(RawNode::element("head", Vec::new()));
+            self.mode = Mode::InHead;
+        }
+    }
+
+    fn close_head(&mut self) {
+        self.open_html();
+        if self.mode == Mode::BeforeHead {
+            self.stack[0].push(RawNode::element("head", Vec::new()));
+        }
+        while self.stack.len() > 1 {
+            self.pop();
+        }
+        self.mode = Mode::AfterHead;
+    }
+
+    fn open_body(&mut self) {
+        if matches!(
+            self.mode,
+            Mode::BeforeHtml | Mode::BeforeHead | Mode::InHead | Mode::AfterHead
+        ) {
+            self.close_head();
+            self.open(RawNode::element("body", Vec::new()));
+            self.mode = Mode::InBody;
+        }
+    }
+
+    /// Closes the nearest open element with the name, unless it is outside the body
+    fn close(&mut self, name: &str) {
+        if !self.is_open(name) {
+            return;
+        }
+        let position = self.stack.iter().rposition(|node| node.name() == name);
+        if let Some(position) = position.filter(|position| *position >= 2) {
+            while self.stack.len() > position {
+                self.pop();
+            }
+        }
+    }
+
+    /// Closes the elements a start tag ends implicitly, such as the previous list item
+    fn imply_end_tags(&mut self, name: &str) {
+        let (closes, boundaries): (&[&str], &[&str]) = match name {
+            "li" => (&["li"], &["ol", "ul", "menu"]),
+            "dd" | "dt" => (&["dd", "dt"], &["dl"]),
+            "tr" => (&["tr"], &["table", "tbody", "thead", "tfoot"]),
+            "td" | "th" => (&["td", "th"], &["tr", "table"]),
+            _ if CLOSES_PARAGRAPH.contains(&name) => (&["p"], SCOPE_BOUNDARIES),
+            _ => return,
+        };
+        if !closes.iter().any(|name| self.is_open(name)) {
+            return;
+        }
+
+        for (index, node) in self.stack.iter().enumerate().rev() {
+            if closes.contains(&node.name()) {
+                while self.stack.len() > index {
+                    self.pop();
+                }
+                return;
+            }
+            if boundaries.contains(&node.name()) || matches!(node.name(), "body" | "html") {
+                return;
+            }
+        }
+    }
+
+    /// Opens the TBODY and TR elements implied by bare rows and cells in a table,
+    /// and closes the open rows and sections before a new section or caption
+    fn imply_table_sections(&mut self, name: &str) {
+        if matches!(
+            name,
+            "caption" | "colgroup" | "col" | "thead" | "tbody" | "tfoot"
+        ) {
+            while matches!(self.current().name(), "tbody" | "thead" | "tfoot" | "tr") {
+                self.pop();
+            }
+        }
+
+        let current = self.current().name().to_string();
+        if current == "table" && matches!(name, "tr" | "td" | "th") {
+            self.open(RawNode::element("tbody", Vec::new()));
+        }
+        let current = self.current().name().to_string();
+        if matches!(current.as_str(), "tbody" | "thead" | "tfoot") && matches!(name, "td" | "th") {
+            self.open(RawNode::element("tr", Vec::new()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(document: &RawNode) -> &RawNode {
+        let RawNode::Document(children) = document else {
+            panic!("expected a document");
+        };
+        let RawNode::Element { children, .. } = &children[0] else {
+            panic!("expected the HTML element");
+        };
+        &children[1]
+    }
+
+    #[test]
+    fn test_implies_document_structure() {
+        let document = parse("<p>Hello</p>");
+        let RawNode::Element { name, children, .. } = body(&document) else {
+            panic!("expected the body");
+        };
+        assert_eq!(name, "body");
+        assert_eq!(children[0].name(), "p");
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b&nbsp;&#8212;&#x41;"),
+            "a & b\u{a0}—A"
+        );
+        assert_eq!(decode_entities("AT&T &unknown;"), "AT&T &unknown;");
+    }
+
+    #[test]
+    fn test_parse_start_tag() {
+        let (name, attributes, self_closing, rest) =
+            parse_start_tag(r#"A HREF="/a?b=1&amp;c=2" data-x=y disabled>text"#);
+        assert_eq!(name, "a");
+        assert_eq!(
+            attributes,
+            vec![
+                ("href".to_string(), "/a?b=1&c=2".to_string()),
+                ("data-x".to_string(), "y".to_string()),
+                ("disabled".to_string(), String::new()),
+            ]
+        );
+        assert!(!self_closing);
+        assert_eq!(rest, "text");
+    }
+}
@@ -1,43 +1,201 @@
-use crate::commonmark_rules;
+use crate::commonmark_rules::ImageTreatment;
+use crate::frontmatter;
 use crate::node::{Node, NodeType};
 use crate::parser;
+use crate::references;
 use crate::rules::{Rule, RuleFilter, Rules};
-use crate::utilities::{trim_leading_newlines, trim_trailing_newlines};
+use crate::utilities::{
+    escape_text, trim_leading_newlines, trim_trailing_newlines, typography_to_ascii,
+};
+use crate::{commonmark_rules, gfm_rules};
 use regex::Regex;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 /// Configuration options for Turndown
+///
+/// With the `serde` feature, options can be loaded from configuration files.
+/// Rules are not serialized and regexes are stored as their pattern.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct Options {
     /// Conversion rules
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub rules: HashMap<String, Rule>,
     /// Style for rendering headings: Setext or Atx (default: Atx)
     pub heading_style: HeadingStyle,
+    /// Levels added to every heading, clamped to 1..=6: positive demotes, so 1 turns
+    /// <h1> into `##`, and negative promotes (default: 0)
+    pub heading_offset: i32,
     /// Used to render horizontal rules (default: * * *)
     pub hr: String,
     /// Marker used for bullet lists (default: *)
     pub bullet_list_marker: String,
+    /// Spaces between a list marker and the item text (default: 1)
+    pub list_marker_spacing: usize,
+    /// Indentation of indented code blocks and of list item continuation lines, by default
+    /// aligning continuations with the item text and indenting code by four spaces
+    /// (default: MarkerWidth)
+    pub indent_style: IndentStyle,
     /// Style for rendering code blocks: Indented or Fenced (default: Fenced)
     pub code_block_style: CodeBlockStyle,
     /// Delimiter used for fenced code blocks (default: ```)
     pub fence: String,
+    /// Detect a fenced code language from a leading shebang or `<!-- lang: x -->` comment
+    /// when no `language-*` class names one (default: false)
+    pub code_language_hints: bool,
     /// Delimiter used for emphasis (default: _)
     pub em_delimiter: String,
     /// Delimiter used for strong emphasis (default: **)
     pub strong_delimiter: String,
+    /// Keep emphasis valid whatever its content: spans of the same kind right next to
+    /// each other are merged, and a span swaps underscores and asterisks when its content
+    /// holds its delimiter character but not the other one (default: false)
+    pub emphasis_safety: bool,
     /// Style for rendering links: Inlined or Referenced (default: Inlined)
     pub link_style: LinkStyle,
     /// Style for link references: Full, Collapsed, or Shortcut (default: Full)
     pub link_reference_style: LinkReferenceStyle,
+    /// Order and labels of the definitions of reference links (default: first appearance, numbered)
+    pub link_reference_format: LinkReferenceFormat,
     /// String used for line breaks (default: two spaces)
     pub br: String,
     /// Options for stripping tracking images (default: false)
     pub strip_tracking_images: bool,
     /// Regex to identify tracking images, comes with a sensible default
+    #[cfg_attr(feature = "serde", serde(with = "regex_pattern"))]
     pub tracking_image_regex: Option<Regex>,
     /// Option to strip images without alt attributes (default: false)
     pub strip_images_without_alt: bool,
+    /// Extra URL heuristics for identifying tracking images (default: all disabled)
+    pub tracking_heuristics: TrackingHeuristics,
+    /// Render the document <title> as a top-level heading instead of dropping it (default: false)
+    pub title_as_heading: bool,
+    /// Start the output with a YAML frontmatter block of the page title, description,
+    /// canonical URL and Open Graph properties from the document head (default: false)
+    pub emit_frontmatter: bool,
+    /// Append image width and height to the alt text, e.g. "Logo (1200x800)" (default: false)
+    pub append_image_dimensions: bool,
+    /// Truncate image alt text and titles longer than this many characters, ending them
+    /// with an ellipsis (default: None)
+    pub max_alt_length: Option<usize>,
+    /// Render emoji images (class "emoji" or a known emoji CDN) as their alt text (default: true)
+    pub emoji_images_as_text: bool,
+    /// Regex to identify emoji images by src, comes with a sensible default
+    #[cfg_attr(feature = "serde", serde(with = "regex_pattern"))]
+    pub emoji_image_regex: Option<Regex>,
+    /// Wrap <bdo dir> content in Unicode bidi override characters (default: false)
+    pub bidi_override_controls: bool,
+    /// Where a <figcaption> is rendered relative to its figure (default: Below)
+    pub figure_caption_placement: CaptionPlacement,
+    /// Where a <caption> is rendered relative to its table (default: Above)
+    pub table_caption_placement: CaptionPlacement,
+    /// Drop links without any visible content instead of emitting `[](url)` (default: true)
+    pub drop_empty_links: bool,
+    /// Link attributes (e.g. target, download) that make a link render as HTML (default: none)
+    pub preserve_link_attributes: Vec<String>,
+    /// Keep links wrapping block content, such as cards, as an HTML <a> around the
+    /// converted blocks instead of a Markdown link (default: false)
+    pub block_links_as_html: bool,
+    /// Merge a link directly followed by an identical one, or an image link followed by a
+    /// text link to the same URL, into a single link (default: false)
+    pub merge_adjacent_links: bool,
+    /// Replace click-tracking redirect links with the URL they embed (default: false)
+    pub unwrap_tracking_redirects: bool,
+    /// Query parameters holding the destination of a redirect link (default: u, url, redirect)
+    pub redirect_url_params: Vec<String>,
+    /// How hidden email preheaders are detected and removed (default: Default)
+    pub preheader_detection: PreheaderMode,
+    /// Class or id substrings and text, such as "unsubscribe" or "view in browser", that mark
+    /// a block as email footer boilerplate to remove, matched ignoring case (default: none)
+    pub boilerplate_patterns: Vec<String>,
+    /// Prefix blockquote attributions (a <footer> inside a quote, or a <cite> directly
+    /// inside it) with an em dash (default: true)
+    pub quote_attribution_dash: bool,
+    /// Append the source URL of a <blockquote cite> as the quote's last line (default: false)
+    pub blockquote_cite: bool,
+    /// Append the cite URL of a <q> inline quote after its closing quote (default: false)
+    pub quote_cite: bool,
+    /// How typographic punctuation such as curly quotes is rendered (default: Preserve)
+    pub typography: TypographyMode,
+    /// How newlines in text are treated: Collapse to spaces or PreserveLineBreaks (default: Collapse)
+    pub whitespace_mode: WhitespaceMode,
+    /// Render bare http(s) URLs in text as autolinks, e.g. `<https://example.com>` (default: false)
+    pub autolink_bare_urls: bool,
+    /// Join text split across <wbr>, text-only <span> wrappers and zero-width characters, as
+    /// obfuscated emails do to break up URLs, before bare URLs are detected (default: false)
+    pub reassemble_split_text: bool,
+    /// Nesting depth beyond which elements are reduced to their text, guarding
+    /// against stack exhaustion on adversarial HTML (default: 256)
+    pub max_depth: usize,
+    /// Render <font> color, size and face as an inline HTML span instead of unwrapping it (default: false)
+    pub font_as_html: bool,
+    /// Render <u> as HTML, as emphasis or as plain text (default: KeepHtml)
+    pub underline_mode: UnderlineMode,
+    /// Render the color of a highlighted <mark> (default: Drop)
+    pub mark_color: MarkColorMode,
+    /// Render <center> and <div align="center"> as plain blocks or keep them as HTML (default: PlainBlock)
+    pub center_mode: CenterMode,
+    /// Render <div>s with a class as Pandoc fenced divs, e.g. `::: warning`, named by their
+    /// first class (default: false)
+    pub pandoc_fenced_divs: bool,
+    /// Keep inline <svg> as HTML instead of dropping it (default: false)
+    pub svg_as_html: bool,
+    /// Render <kbd> keys as a code span (`Ctrl+C`) or keep them as HTML (default: Code)
+    pub kbd_style: KbdStyle,
+    /// Render <samp> sample output as a code span (default: true)
+    pub samp_as_code: bool,
+    /// Render <output> results as a code span instead of plain text (default: false)
+    pub output_as_code: bool,
+    /// Templates rendering elements by tag name, where `{text}` is the converted content and
+    /// `{name}` the value of an attribute, e.g. `time` → `{text} ({datetime})` (default: none)
+    pub semantic_templates: HashMap<String, String>,
+    /// Attribute, such as `data-md-raw`, marking elements whose Markdown is written verbatim:
+    /// the attribute's value, or the element's text as written when it is empty (default: None)
+    pub raw_markdown_attribute: Option<String>,
+    /// Render tables that look like email layout as stacked blocks instead of pipe tables (default: false)
+    pub detect_layout_tables: bool,
+    /// Render table cells holding blocks such as paragraphs or lists with their lines joined
+    /// by <br>, or keep the whole table as HTML (default: FlattenWithBr)
+    pub complex_table_mode: ComplexTableMode,
+    /// Skip pipe table rows whose cells are all blank, such as spacer rows of &nbsp; (default: true)
+    pub drop_empty_table_rows: bool,
+    /// Drop spacer list items holding only whitespace or NBSP instead of emitting a lone marker (default: true)
+    pub drop_empty_list_items: bool,
+    /// Strip list markers typed into item text, such as `1.` or `•`, when every item of the list has one (default: false)
+    pub dedupe_manual_list_markers: bool,
+    /// Pick bullet markers from the `list-style-type` of lists: `*` for disc, `-` for circle, `+` for square (default: false)
+    pub list_style_markers: bool,
+    /// Keep an extra blank line where an empty spacer paragraph or div separated two blocks (default: false)
+    pub preserve_empty_block_spacing: bool,
+    /// Separator, such as a thematic break, placed between top-level sibling <article>/<section> blocks (default: None)
+    pub section_separator: Option<String>,
+    /// Parser used to read the HTML (default: Html5ever when the `html5ever` feature is enabled)
+    pub parser: ParserKind,
+}
+
+/// Serializes optional regexes as their pattern, compiling them again on load
+#[cfg(feature = "serde")]
+mod regex_pattern {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        regex: &Option<Regex>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        regex.as_ref().map(Regex::as_str).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Regex>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+            .transpose()
+    }
 }
 
 impl fmt::Debug for Options {
@@ -45,14 +203,20 @@ impl fmt::Debug for Options {
         f.debug_struct("Options")
             .field("rules", &self.rules)
             .field("heading_style", &self.heading_style)
+            .field("heading_offset", &self.heading_offset)
             .field("hr", &self.hr)
             .field("bullet_list_marker", &self.bullet_list_marker)
+            .field("list_marker_spacing", &self.list_marker_spacing)
+            .field("indent_style", &self.indent_style)
             .field("code_block_style", &self.code_block_style)
             .field("fence", &self.fence)
+            .field("code_language_hints", &self.code_language_hints)
             .field("em_delimiter", &self.em_delimiter)
             .field("strong_delimiter", &self.strong_delimiter)
+            .field("emphasis_safety", &self.emphasis_safety)
             .field("link_style", &self.link_style)
             .field("link_reference_style", &self.link_reference_style)
+            .field("link_reference_format", &self.link_reference_format)
             .field("br", &self.br)
             .field("strip_tracking_images", &self.strip_tracking_images)
             .field(
@@ -60,35 +224,324 @@ impl fmt::Debug for Options {
                 &self.tracking_image_regex.as_ref().map(|_| "<regex>"),
             )
             .field("strip_images_without_alt", &self.strip_images_without_alt)
+            .field("tracking_heuristics", &self.tracking_heuristics)
+            .field("title_as_heading", &self.title_as_heading)
+            .field("emit_frontmatter", &self.emit_frontmatter)
+            .field("append_image_dimensions", &self.append_image_dimensions)
+            .field("max_alt_length", &self.max_alt_length)
+            .field("emoji_images_as_text", &self.emoji_images_as_text)
+            .field(
+                "emoji_image_regex",
+                &self.emoji_image_regex.as_ref().map(|_| "<regex>"),
+            )
+            .field("bidi_override_controls", &self.bidi_override_controls)
+            .field("figure_caption_placement", &self.figure_caption_placement)
+            .field("table_caption_placement", &self.table_caption_placement)
+            .field("drop_empty_links", &self.drop_empty_links)
+            .field("preserve_link_attributes", &self.preserve_link_attributes)
+            .field("block_links_as_html", &self.block_links_as_html)
+            .field("merge_adjacent_links", &self.merge_adjacent_links)
+            .field("unwrap_tracking_redirects", &self.unwrap_tracking_redirects)
+            .field("redirect_url_params", &self.redirect_url_params)
+            .field("preheader_detection", &self.preheader_detection)
+            .field("boilerplate_patterns", &self.boilerplate_patterns)
+            .field("quote_attribution_dash", &self.quote_attribution_dash)
+            .field("blockquote_cite", &self.blockquote_cite)
+            .field("quote_cite", &self.quote_cite)
+            .field("typography", &self.typography)
+            .field("whitespace_mode", &self.whitespace_mode)
+            .field("autolink_bare_urls", &self.autolink_bare_urls)
+            .field("reassemble_split_text", &self.reassemble_split_text)
+            .field("max_depth", &self.max_depth)
+            .field("font_as_html", &self.font_as_html)
+            .field("underline_mode", &self.underline_mode)
+            .field("mark_color", &self.mark_color)
+            .field("center_mode", &self.center_mode)
+            .field("pandoc_fenced_divs", &self.pandoc_fenced_divs)
+            .field("svg_as_html", &self.svg_as_html)
+            .field("kbd_style", &self.kbd_style)
+            .field("samp_as_code", &self.samp_as_code)
+            .field("output_as_code", &self.output_as_code)
+            .field("semantic_templates", &self.semantic_templates)
+            .field("raw_markdown_attribute", &self.raw_markdown_attribute)
+            .field("detect_layout_tables", &self.detect_layout_tables)
+            .field("complex_table_mode", &self.complex_table_mode)
+            .field("drop_empty_table_rows", &self.drop_empty_table_rows)
+            .field("drop_empty_list_items", &self.drop_empty_list_items)
+            .field(
+                "dedupe_manual_list_markers",
+                &self.dedupe_manual_list_markers,
+            )
+            .field("list_style_markers", &self.list_style_markers)
+            .field(
+                "preserve_empty_block_spacing",
+                &self.preserve_empty_block_spacing,
+            )
+            .field("section_separator", &self.section_separator)
+            .field("parser", &self.parser)
             .finish()
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HeadingStyle {
     Setext,
     Atx,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CodeBlockStyle {
     Indented,
     Fenced,
 }
 
+/// Indentation of nested Markdown blocks
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IndentStyle {
+    /// List item continuation lines are aligned with the text after the
+    /// marker, and indented code blocks use four spaces
+    MarkerWidth,
+    /// The given number of spaces for both. Markdown only reads list item
+    /// continuations indented to the marker width and code blocks indented
+    /// by four spaces or more
+    Spaces(usize),
+    /// A hard tab for each level of indentation
+    Tab,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LinkStyle {
     Inlined,
     Referenced,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LinkReferenceStyle {
     Full,
     Collapsed,
     Shortcut,
 }
 
+/// Formatting of the definitions appended for reference links
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LinkReferenceFormat {
+    /// Order of the definitions
+    pub order: ReferenceOrder,
+    /// Labels of `Full` style references; the other styles use the link text
+    pub labels: ReferenceLabels,
+}
+
+impl Default for LinkReferenceFormat {
+    fn default() -> Self {
+        LinkReferenceFormat {
+            order: ReferenceOrder::FirstAppearance,
+            labels: ReferenceLabels::Numeric,
+        }
+    }
+}
+
+/// Order of reference link definitions
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReferenceOrder {
+    /// In the order the links first appear
+    FirstAppearance,
+    /// Sorted alphabetically by URL
+    ByUrl,
+    /// Grouped by host, in the order each host first appears
+    GroupedByHost,
+}
+
+/// Labels of `Full` style reference links
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReferenceLabels {
+    /// Numbered from 1, e.g. `[text][1]`
+    Numeric,
+    /// Derived from the link text, e.g. `[Release notes][release-notes]`
+    Slug,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CaptionPlacement {
+    Above,
+    Below,
+}
+
+/// Rendering of typographic punctuation in text
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TypographyMode {
+    /// Keep curly quotes, dashes and ellipses as written
+    Preserve,
+    /// Replace them with their plain ASCII equivalents
+    ToAscii,
+}
+
+/// Rendering of <u> underlines, which have no Markdown equivalent
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnderlineMode {
+    /// Plain text, losing the underline
+    Drop,
+    /// An inline `<u>` HTML element around the content
+    KeepHtml,
+    /// Emphasis, using the emphasis delimiter
+    Emphasis,
+}
+
+/// Rendering of the color of a <mark> highlight, given by a `data-color`
+/// attribute or a background style
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MarkColorMode {
+    /// Plain `==text==`, losing the color
+    Drop,
+    /// `==text==` followed by the color in an HTML comment
+    Comment,
+    /// The `<mark>` element kept as inline HTML
+    KeepHtml,
+}
+
+/// Rendering of centered blocks, which Markdown has no syntax for
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CenterMode {
+    /// A plain block, losing the centering
+    PlainBlock,
+    /// The converted content between the element's HTML tags, set apart by
+    /// blank lines so the content is still read as Markdown
+    KeepHtml,
+}
+
+/// Rendering of tables with block content, such as paragraphs or lists, in their
+/// cells, which a pipe table row cannot hold
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ComplexTableMode {
+    /// A pipe table, each cell with the lines of its blocks joined by `<br>`
+    FlattenWithBr,
+    /// The table as HTML, unconverted
+    KeepHtml,
+}
+
+/// Handling of newlines in text outside of preformatted content
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WhitespaceMode {
+    /// Newlines collapse to spaces, as browsers render them
+    Collapse,
+    /// Newlines become hard line breaks, as with `white-space: pre-line`
+    PreserveLineBreaks,
+}
+
+/// Rendering of <kbd> keyboard input
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KbdStyle {
+    /// A code span, with key combos such as `<kbd>Ctrl</kbd>+<kbd>C</kbd>` joined into one
+    Code,
+    /// The original HTML
+    Html,
+}
+
+/// HTML parser used to build the document tree
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParserKind {
+    /// The spec-compliant html5ever parser
+    #[cfg(feature = "html5ever")]
+    Html5ever,
+    /// A lightweight parser for well-formed HTML, enabled by the `simple-parser` feature
+    #[cfg(feature = "simple-parser")]
+    Simple,
+}
+
+impl Default for ParserKind {
+    fn default() -> Self {
+        #[cfg(feature = "html5ever")]
+        return ParserKind::Html5ever;
+        #[cfg(not(feature = "html5ever"))]
+        return ParserKind::Simple;
+    }
+}
+
+/// URL heuristics for tracking images, applied when stripping tracking images
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TrackingHeuristics {
+    /// Flag URLs whose query string is longer than this many characters
+    pub max_query_length: Option<usize>,
+    /// Flag URLs whose query requests a 1x1 size (e.g. `w=1&h=1`)
+    pub size_hint_in_url: bool,
+    /// Flag URLs carrying any query key starting with one of these (e.g. `utm_`, `mid`, `eid`)
+    pub query_keys: Vec<String>,
+}
+
+impl TrackingHeuristics {
+    /// Checks if an image URL looks like a tracking image
+    pub fn matches(&self, src: &str) -> bool {
+        let query = match src.split_once('?') {
+            Some((_, query)) => query.split('#').next().unwrap_or_default(),
+            None => return false,
+        };
+
+        if self
+            .max_query_length
+            .map_or(false, |max| query.chars().count() > max)
+        {
+            return true;
+        }
+
+        let params: Vec<(String, &str)> = query
+            .split('&')
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (key.to_lowercase(), value)
+            })
+            .collect();
+
+        if self.size_hint_in_url {
+            let is_one = |keys: &[&str]| {
+                params
+                    .iter()
+                    .any(|(key, value)| keys.contains(&key.as_str()) && *value == "1")
+            };
+            if is_one(&["w", "width"]) && is_one(&["h", "height"]) {
+                return true;
+            }
+        }
+
+        params.iter().any(|(key, _)| {
+            self.query_keys
+                .iter()
+                .any(|prefix| !prefix.is_empty() && key.starts_with(&prefix.to_lowercase()))
+        })
+    }
+}
+
+/// Detection of hidden email preheaders, which are removed from the output
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PreheaderMode {
+    /// Keep preheaders as regular content
+    Off,
+    /// Detect preheaders by their data attribute and hiding styles
+    Default,
+    /// Treat every node the function matches as a preheader
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(fn(&Node) -> bool),
+}
+
 impl Default for Options {
     fn default() -> Self {
         // Create default tracking image regex with common tracking indicators
@@ -98,39 +551,225 @@ impl Default for Options {
         )
         .ok();
 
+        // Known CDNs serving emoji images
+        let emoji_regex = Regex::new(
+            r"(?i)(twemoji|s\.w\.org/images/core/emoji|githubassets\.com/images/icons/emoji|emoji\.slack-edge\.com|fbcdn\.net/images/emoji)",
+        )
+        .ok();
+
         Options {
             rules: commonmark_rules::get_rules(),
             heading_style: HeadingStyle::Atx,
+            heading_offset: 0,
             hr: "* * *".to_string(),
             bullet_list_marker: "*".to_string(),
+            list_marker_spacing: 1,
+            indent_style: IndentStyle::MarkerWidth,
             code_block_style: CodeBlockStyle::Fenced,
             fence: "```".to_string(),
+            code_language_hints: false,
             em_delimiter: "_".to_string(),
             strong_delimiter: "**".to_string(),
+            emphasis_safety: false,
             link_style: LinkStyle::Inlined,
             link_reference_style: LinkReferenceStyle::Full,
+            link_reference_format: LinkReferenceFormat::default(),
             br: "  ".to_string(),
             strip_tracking_images: false,
             tracking_image_regex: tracking_regex,
             strip_images_without_alt: false,
+            tracking_heuristics: TrackingHeuristics::default(),
+            title_as_heading: false,
+            emit_frontmatter: false,
+            append_image_dimensions: false,
+            max_alt_length: None,
+            emoji_images_as_text: true,
+            emoji_image_regex: emoji_regex,
+            bidi_override_controls: false,
+            figure_caption_placement: CaptionPlacement::Below,
+            table_caption_placement: CaptionPlacement::Above,
+            drop_empty_links: true,
+            preserve_link_attributes: Vec::new(),
+            block_links_as_html: false,
+            merge_adjacent_links: false,
+            unwrap_tracking_redirects: false,
+            redirect_url_params: vec!["u".to_string(), "url".to_string(), "redirect".to_string()],
+            preheader_detection: PreheaderMode::Default,
+            boilerplate_patterns: Vec::new(),
+            quote_attribution_dash: true,
+            blockquote_cite: false,
+            quote_cite: false,
+            typography: TypographyMode::Preserve,
+            whitespace_mode: WhitespaceMode::Collapse,
+            autolink_bare_urls: false,
+            reassemble_split_text: false,
+            max_depth: 256,
+            font_as_html: false,
+            underline_mode: UnderlineMode::KeepHtml,
+            mark_color: MarkColorMode::Drop,
+            center_mode: CenterMode::PlainBlock,
+            pandoc_fenced_divs: false,
+            svg_as_html: false,
+            kbd_style: KbdStyle::Code,
+            samp_as_code: true,
+            output_as_code: false,
+            semantic_templates: HashMap::new(),
+            raw_markdown_attribute: None,
+            detect_layout_tables: false,
+            complex_table_mode: ComplexTableMode::FlattenWithBr,
+            drop_empty_table_rows: true,
+            drop_empty_list_items: true,
+            dedupe_manual_list_markers: false,
+            list_style_markers: false,
+            preserve_empty_block_spacing: false,
+            section_separator: None,
+            parser: ParserKind::default(),
         }
     }
 }
 
+impl Options {
+    /// Creates options for GitHub Flavored Markdown, with the GFM rules merged
+    /// over the CommonMark ones
+    pub fn with_gfm() -> Self {
+        let mut options = Options::default();
+        options.rules.extend(gfm_rules::get_rules());
+        options
+    }
+}
+
 pub type TurndownOptions = Options;
 
+/// A transformation applied to the parsed tree before conversion
+pub type Preprocessor = Box<dyn Fn(&mut Node) + Send + Sync>;
+
+/// A transformation applied to the final Markdown output
+pub type PostProcessor = Box<dyn Fn(String) -> String + Send + Sync>;
+
 /// Main turndown for converting HTML to Markdown
 pub struct Turndown {
     pub options: TurndownOptions,
     pub rules: Rules,
-    escape_patterns: Vec<(Regex, String)>,
+    bare_url_pattern: Regex,
+    preprocessor: Option<Preprocessor>,
+    post_processor: Option<PostProcessor>,
+}
+
+/// Counts of notable elements seen during a conversion
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConvertStats {
+    /// Links rendered to Markdown
+    pub links: usize,
+    /// Images rendered to Markdown
+    pub images: usize,
+    /// Headings of any level
+    pub headings: usize,
+    /// Code blocks (PRE elements)
+    pub code_blocks: usize,
+    /// Tables
+    pub tables: usize,
+    /// Images dropped by `strip_tracking_images` for matching the tracking regex or heuristics
+    pub stripped_tracking_images: usize,
+}
+
+impl ConvertStats {
+    /// Records the rendered output of an element
+    fn record(&mut self, node: &Node, output: &str, options: &TurndownOptions) {
+        let rendered = !output.trim().is_empty();
+        match node.node_name.as_str() {
+            "A" if rendered && node.get_attribute("href").is_some() => self.links += 1,
+            "IMG" => match commonmark_rules::image_treatment(node, options) {
+                ImageTreatment::Tracking => self.stripped_tracking_images += 1,
+                ImageTreatment::Image if rendered => self.images += 1,
+                _ => {}
+            },
+            "H1" | "H2" | "H3" | "H4" | "H5" | "H6" if rendered => self.headings += 1,
+            "PRE" if rendered => self.code_blocks += 1,
+            "TABLE" => self.tables += 1,
+            _ => {}
+        }
+    }
 }
 
 /// Context for list processing
 #[derive(Clone, Debug)]
 struct ListContext {
-    pub list_type: String, // "OL" or "UL"
-    pub item_index: usize, // 1-based index for items
+    pub list_type: String, // "OL", "UL" or "MENU"
+    pub item_index: usize, // Number of the item, from the list start or an item value
+    /// The CSS `list-style-type` of the list, or its legacy `type` attribute
+    pub style: Option<String>,
+}
+
+/// Context inherited from ancestor elements while processing
+#[derive(Clone, Debug, Default)]
+struct NodeContext {
+    pub list: Option<ListContext>,
+    pub in_pre: bool,
+    pub in_blockquote: bool,
+    pub in_link: bool,
+    pub table_columns: Option<usize>,
+    pub in_table_cell: bool,
+    /// Set for an emphasis element directly adjacent to the letters of a word
+    pub intraword: bool,
+    /// Inside an odd number of emphasis elements, whose delimiter must alternate
+    pub nested_emphasis: bool,
+    /// Inside an odd number of strong elements, whose delimiter must alternate
+    pub nested_strong: bool,
+    /// Inside an odd number of <q> elements, whose quotes must alternate
+    pub nested_quote: bool,
+    /// Inside an <article> or <section>
+    pub in_section: bool,
+}
+
+impl NodeContext {
+    /// Derives the context for the children of a node
+    fn enter(&self, node: &Node, options: &TurndownOptions) -> NodeContext {
+        let name = node.node_name.as_str();
+        let list = if matches!(name, "OL" | "UL" | "MENU") {
+            Some(ListContext {
+                list_type: node.node_name.clone(),
+                item_index: 0,
+                style: node.list_style_type(),
+            })
+        } else {
+            self.list.clone()
+        };
+
+        NodeContext {
+            list,
+            in_pre: self.in_pre || node.is_preformatted(),
+            in_blockquote: self.in_blockquote || name == "BLOCKQUOTE",
+            in_link: self.in_link || name == "A",
+            table_columns: match name {
+                // Layout tables are not tables to the rules, so their cells are plain blocks
+                "TABLE" if gfm_rules::is_layout_table(node, options) => None,
+                "TABLE" => Some(node.table_column_count()),
+                _ => self.table_columns,
+            },
+            in_table_cell: match name {
+                "TABLE" => false,
+                "TD" | "TH" => self.table_columns.is_some(),
+                _ => self.in_table_cell,
+            },
+            intraword: false,
+            nested_emphasis: self.nested_emphasis
+                ^ (matches!(name, "EM" | "I")
+                    || (name == "U" && options.underline_mode == UnderlineMode::Emphasis)),
+            nested_strong: self.nested_strong ^ matches!(name, "STRONG" | "B"),
+            nested_quote: self.nested_quote ^ (name == "Q"),
+            in_section: self.in_section || matches!(name, "ARTICLE" | "SECTION"),
+        }
+    }
+
+    /// Checks if blocks here are top-level Markdown blocks, outside lists,
+    /// quotes, code, links and tables
+    fn is_top_level(&self) -> bool {
+        self.list.is_none()
+            && !self.in_pre
+            && !self.in_blockquote
+            && !self.in_link
+            && self.table_columns.is_none()
+    }
 }
 
 impl Turndown {
@@ -143,120 +782,308 @@ impl Turndown {
     pub fn with_options(options: TurndownOptions) -> Self {
         let rules = Rules::new(options.clone());
 
-        let escape_patterns = vec![
-            (Regex::new(r"\\").unwrap(), "\\\\".to_string()),
-            (Regex::new(r"\*").unwrap(), "\\*".to_string()),
-            (Regex::new(r"^-").unwrap(), "\\-".to_string()),
-            (Regex::new(r"^\+ ").unwrap(), "\\+ ".to_string()),
-            (Regex::new(r"^(=+)").unwrap(), "\\$1".to_string()),
-            (Regex::new(r"^(#{1,6}) ").unwrap(), "\\$1 ".to_string()),
-            (Regex::new(r"`").unwrap(), "\\`".to_string()),
-            (Regex::new(r"^~~~").unwrap(), "\\~~~".to_string()),
-            (Regex::new(r"\[").unwrap(), "\\[".to_string()),
-            (Regex::new(r"\]").unwrap(), "\\]".to_string()),
-            (Regex::new(r"^>").unwrap(), "\\>".to_string()),
-            (Regex::new(r"_").unwrap(), "\\_".to_string()),
-            (Regex::new(r"^(\d+)\. ").unwrap(), "$1\\. ".to_string()),
-        ];
-
         Turndown {
             options,
             rules,
-            escape_patterns,
+            bare_url_pattern: Regex::new(r"https?://[^\s<>]+").unwrap(),
+            preprocessor: None,
+            post_processor: None,
         }
     }
 
     /// Converts HTML to Markdown
     pub fn convert(&self, html: &str) -> String {
+        self.convert_with_stats(html).0
+    }
+
+    /// Converts HTML to Markdown and reports counts of the elements converted
+    pub fn convert_with_stats(&self, html: &str) -> (String, ConvertStats) {
+        let mut stats = ConvertStats::default();
+        if html.is_empty() {
+            return (String::new(), stats);
+        }
+
+        let root = self.parse(html);
+        let markdown = self.convert_tree(&root, &mut stats);
+        (markdown, stats)
+    }
+
+    /// Converts HTML to Markdown, split into sections at top-level horizontal rules.
+    /// Each section is converted as a document of its own, so it has its own
+    /// reference definitions and the post-processor runs once per section
+    pub fn convert_sections(&self, html: &str) -> Vec<String> {
         if html.is_empty() {
-            return String::new();
+            return Vec::new();
+        }
+
+        let root = self.parse(html);
+        let body = find_body(&root).unwrap_or(&root);
+
+        let mut groups = vec![Vec::new()];
+        for child in &body.children {
+            if child.node_name == "HR" {
+                groups.push(Vec::new());
+            } else if let Some(group) = groups.last_mut() {
+                group.push(child.clone());
+            }
+        }
+
+        let mut stats = ConvertStats::default();
+        groups
+            .into_iter()
+            .map(|children| {
+                let mut section = Node::new_element("body");
+                section.children = children;
+                self.convert_tree(&section, &mut stats)
+            })
+            .filter(|markdown| !markdown.is_empty())
+            .collect()
+    }
+
+    /// Extracts the data of every table, nested ones included, in document order.
+    /// Each table is a list of rows holding the Markdown of their cells, with an
+    /// empty cell after a cell for every further column it spans. Links in cells
+    /// are always inline, and the post-processor does not run on them
+    pub fn extract_tables(&self, html: &str) -> Vec<Vec<Vec<String>>> {
+        if html.is_empty() {
+            return Vec::new();
+        }
+
+        let root = self.parse(html);
+        let mut tables = Vec::new();
+        let mut stack = vec![&root];
+        while let Some(node) = stack.pop() {
+            if node.node_name == "TABLE" {
+                tables.push(node);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+
+        let mut stats = ConvertStats::default();
+        tables
+            .into_iter()
+            .map(|table| {
+                table
+                    .table_rows()
+                    .into_iter()
+                    .map(|row| {
+                        let mut cells = Vec::new();
+                        for cell in &row.children {
+                            if !matches!(cell.node_name.as_str(), "TD" | "TH") {
+                                continue;
+                            }
+                            // Cells hold only their own text, so reference links are
+                            // inlined and the post-processor is left to documents
+                            let mut content = Node::new_element("body");
+                            content.children = cell.children.clone();
+                            let output = self.process_with_context(&content, &mut stats);
+                            cells.push(self.post_process(&references::inline(&output)));
+                            cells.extend((1..cell.column_span()).map(|_| String::new()));
+                        }
+                        cells
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Parses HTML and applies the preprocessor
+    fn parse(&self, html: &str) -> Node {
+        let mut root = parser::parse_html_with_options(html, &self.options);
+        resolve_form_labels(&mut root);
+        mark_quote_attributions(&mut root);
+        if self.options.dedupe_manual_list_markers {
+            dedupe_manual_list_markers(&mut root, self.options.drop_empty_list_items);
+        }
+        if self.options.emphasis_safety {
+            merge_adjacent_emphasis(&mut root);
+        }
+        if self.options.merge_adjacent_links {
+            merge_adjacent_links(&mut root);
+        }
+        if self.options.reassemble_split_text {
+            reassemble_split_text(&mut root);
+        }
+        if let Some(preprocessor) = &self.preprocessor {
+            preprocessor(&mut root);
+        }
+        root
+    }
+
+    /// Converts a parsed tree to final Markdown
+    fn convert_tree(&self, root: &Node, stats: &mut ConvertStats) -> String {
+        let mut output = self.process_with_context(root, stats);
+        if self.options.link_style == LinkStyle::Referenced {
+            output = references::resolve(&output, &self.options);
+        }
+        let mut markdown = self.post_process(&output);
+        if self.options.emit_frontmatter {
+            let entries = frontmatter::collect(root);
+            if !entries.is_empty() {
+                let block = frontmatter::render(&entries);
+                markdown = if markdown.is_empty() {
+                    block
+                } else {
+                    format!("{}\n\n{}", block, markdown)
+                };
+            }
         }
 
-        let root = parser::parse_html(html);
-        let output = self.process_with_context(&root, None);
-        self.post_process(&output)
+        match &self.post_processor {
+            Some(post_processor) => post_processor(markdown),
+            None => markdown,
+        }
     }
 
-    /// Processes a node and its children recursively with optional list context
-    fn process_with_context(&self, node: &Node, list_context: Option<ListContext>) -> String {
-        self.process_with_full_context(node, list_context, false)
+    /// Processes a node and its children recursively from the document root
+    fn process_with_context(&self, node: &Node, stats: &mut ConvertStats) -> String {
+        self.process_with_full_context(node, &NodeContext::default(), stats)
     }
 
     /// Processes a node and its children recursively with full context
     fn process_with_full_context(
         &self,
         node: &Node,
-        list_context: Option<ListContext>,
-        in_pre: bool,
+        context: &NodeContext,
+        stats: &mut ConvertStats,
     ) -> String {
         let mut output = String::new();
-        let mut item_index = 0;
+        // Items are numbered from the list's start, and an item with a value
+        // renumbers the items after it
+        let mut next_number = list_start(node);
 
-        // Determine if this is a list element
-        let is_list = matches!(node.node_name.as_str(), "OL" | "UL");
-        let new_list_context = if is_list {
-            Some(ListContext {
-                list_type: node.node_name.clone(),
-                item_index: 0,
-            })
-        } else {
-            list_context.clone()
-        };
+        // Determine the list, PRE and blockquote context for the children
+        let child_context = context.enter(node, &self.options);
 
-        // Determine if we're entering a PRE block
-        let new_in_pre = in_pre || node.node_name == "PRE";
+        let mut merged = merge_text_runs(self.ordered_children(node));
+        if self.options.kbd_style == KbdStyle::Code && node.node_name != "KBD" {
+            merged = merge_key_combos(merged);
+        }
+        let children: Vec<&Node> = merged.iter().map(|child| child.as_ref()).collect();
+        for (index, child) in children.iter().copied().enumerate() {
+            if let Some(separator) = &self.options.section_separator {
+                if !child_context.in_section && follows_section(&children, index) {
+                    output = self.join(&output, &format!("\n\n{}\n\n", separator));
+                }
+            }
 
-        for child in &node.children {
             let replacement = if child.node_type == NodeType::Text {
-                if child.is_code {
+                // Code is emitted verbatim, however many elements it is split across
+                if child.is_code || child_context.in_pre {
                     child.node_value.clone()
                 } else {
-                    self.escape(&child.node_value)
+                    let text = match self.options.typography {
+                        TypographyMode::Preserve => child.node_value.clone(),
+                        TypographyMode::ToAscii => typography_to_ascii(&child.node_value),
+                    };
+                    // Text at the start of a line loses its indent, so line-start
+                    // markers such as `-` or `1.` in it are escaped; flanking
+                    // whitespace restores the space before an inline element
+                    let text = if output.is_empty() || output.ends_with('\n') {
+                        text.trim_start_matches(' ')
+                    } else {
+                        &text
+                    };
+                    let escaped = self.escape(text);
+                    if self.options.autolink_bare_urls && !child_context.in_link {
+                        self.autolink(&escaped)
+                    } else {
+                        escaped
+                    }
                 }
+            } else if self.options.preserve_empty_block_spacing
+                && child_context.is_top_level()
+                && matches!(child.node_name.as_str(), "P" | "DIV")
+                && child.is_spacer()
+            {
+                // Marks the spacer so its blank line survives newline collapsing
+                format!("\n\n{}\n\n", BLOCK_SPACER)
+            } else if child.node_name == "BR" && is_stray_line_break(&children, index) {
+                // A hard break between blocks has no line to break
+                String::new()
             } else if child.node_type == NodeType::Element {
                 // Increment item index for LI elements
-                if child.node_name == "LI" && new_list_context.is_some() {
-                    item_index += 1;
-                    let mut context_with_index = new_list_context.clone().unwrap();
-                    context_with_index.item_index = item_index;
-                    self.replacement_for_node_with_full_context(
-                        child,
-                        Some(context_with_index),
-                        new_in_pre,
-                    )
+                if child.node_name == "LI" && child_context.list.is_some() {
+                    // Spacer items are skipped without taking up a number
+                    if self.options.drop_empty_list_items && child.is_spacer() {
+                        continue;
+                    }
+                    let number = list_item_value(child).unwrap_or(next_number);
+                    next_number = number.saturating_add(1);
+                    let mut context_with_index = child_context.clone();
+                    if let Some(list) = context_with_index.list.as_mut() {
+                        list.item_index = number;
+                    }
+                    self.replacement_for_node_with_full_context(child, &context_with_index, stats)
+                } else if is_intraword(&children, index) {
+                    let mut context_intraword = child_context.clone();
+                    context_intraword.intraword = true;
+                    self.replacement_for_node_with_full_context(child, &context_intraword, stats)
                 } else {
-                    self.replacement_for_node_with_full_context(
-                        child,
-                        new_list_context.clone(),
-                        new_in_pre,
-                    )
+                    self.replacement_for_node_with_full_context(child, &child_context, stats)
                 }
             } else {
                 String::new()
             };
 
-            output = self.join(&output, &replacement);
+            // Flanking whitespace moved out of an element must not double the
+            // space already between it and its neighbours
+            let is_code = child.is_code || node.node_name == "CODE";
+            let replacement = if output.ends_with(' ') && !child_context.in_pre && !is_code {
+                replacement.trim_start_matches(' ')
+            } else {
+                &replacement
+            };
+
+            // Newlines in preformatted content are kept as they are rather than merged
+            if child_context.in_pre {
+                output.push_str(replacement);
+            } else {
+                output = self.join(&output, replacement);
+            }
         }
 
         output
     }
 
+    /// Gets the children of a node in rendering order, moving figure and
+    /// table captions to their configured placement
+    fn ordered_children<'a>(&self, node: &'a Node) -> Vec<&'a Node> {
+        let (caption_name, placement) = match node.node_name.as_str() {
+            "FIGURE" => ("FIGCAPTION", &self.options.figure_caption_placement),
+            "TABLE" => ("CAPTION", &self.options.table_caption_placement),
+            _ => return node.children.iter().collect(),
+        };
+
+        let (captions, mut children): (Vec<&Node>, Vec<&Node>) = node
+            .children
+            .iter()
+            .partition(|child| child.node_name == caption_name);
+
+        match placement {
+            CaptionPlacement::Above => {
+                children.splice(0..0, captions);
+            }
+            CaptionPlacement::Below => children.extend(captions),
+        }
+        children
+    }
+
     /// Gets replacement for an element node with full context
     fn replacement_for_node_with_full_context(
         &self,
         node: &Node,
-        list_context: Option<ListContext>,
-        in_pre: bool,
+        context: &NodeContext,
+        stats: &mut ConvertStats,
     ) -> String {
-        let new_in_pre = in_pre || node.node_name == "PRE";
-        let mut content = self.process_with_full_context(node, list_context.clone(), new_in_pre);
+        let mut content = self.process_with_full_context(node, context, stats);
 
         let whitespace = node.flanking_whitespace();
 
         let is_table_cell = matches!(node.node_name.as_str(), "TD" | "TH");
 
         if node.is_block() {
-            content = content.trim_start().to_string();
+            content = trim_leading_newlines(&content).to_string();
         }
 
         let (use_leading, use_trailing) = if is_table_cell || node.is_block() {
@@ -265,37 +1092,109 @@ impl Turndown {
             (whitespace.leading.clone(), whitespace.trailing.clone())
         };
 
-        if !whitespace.leading.is_empty() || !whitespace.trailing.is_empty() {
+        // Preformatted content keeps the whitespace at its edges
+        let trims_edges = node.node_name != "PRE";
+        if trims_edges && (!whitespace.leading.is_empty() || !whitespace.trailing.is_empty()) {
             content = content.trim().to_string();
         }
 
         let mut node_with_context = node.clone();
-        if let Some(ctx) = list_context {
+        if let Some(ctx) = &context.list {
             node_with_context.set_attribute("data-list-type", &ctx.list_type);
+            if let Some(style) = &ctx.style {
+                node_with_context.set_attribute("data-list-style", style);
+            }
             node_with_context.set_attribute("data-list-index", &ctx.item_index.to_string());
         }
-        if new_in_pre {
+        if context.in_pre || node.node_name == "PRE" {
             node_with_context.set_attribute("data-in-pre", "true");
         }
+        if context.in_blockquote {
+            node_with_context.set_attribute("data-in-blockquote", "true");
+        }
+        if let Some(columns) = context.table_columns {
+            node_with_context.set_attribute("data-table-columns", &columns.to_string());
+        }
+        if context.in_table_cell {
+            node_with_context.set_attribute("data-in-table", "true");
+        }
+        if context.intraword {
+            node_with_context.set_attribute("data-intraword", "true");
+        }
+        if context.nested_emphasis {
+            node_with_context.set_attribute("data-nested-emphasis", "true");
+        }
+        if context.nested_strong {
+            node_with_context.set_attribute("data-nested-strong", "true");
+        }
+        if context.nested_quote {
+            node_with_context.set_attribute("data-nested-quote", "true");
+        }
 
         let rule = self.rules.for_node(&node_with_context);
+        let replacement = (rule.replacement)(&content, &node_with_context, &self.options);
+        stats.record(node, &replacement, &self.options);
 
-        format!(
-            "{}{}{}",
-            use_leading,
-            (rule.replacement)(&content, &node_with_context, &self.options),
-            use_trailing
-        )
+        format!("{}{}{}", use_leading, replacement, use_trailing)
     }
 
     /// Post-processes the output
     fn post_process(&self, output: &str) -> String {
-        let collapsed = self.collapse_excessive_newlines(output);
+        let mut collapsed = self.collapse_excessive_newlines(output);
+        if self.options.preserve_empty_block_spacing {
+            collapsed = expand_block_spacers(&collapsed);
+        }
+        // Leading tabs are kept, as they indent a code block opening the document
         let trimmed = collapsed
-            .trim_start_matches(|c| c == '\t' || c == '\r' || c == '\n')
-            .trim_end_matches(|c| c == '\t' || c == '\r' || c == '\n' || c == ' ');
+            .trim_start_matches(['\r', '\n'])
+            .trim_end_matches(['\t', '\r', '\n']);
 
-        trimmed.to_string()
+        self.trim_trailing_whitespace(trimmed)
+    }
+
+    /// Strips trailing whitespace from every line outside code blocks, keeping
+    /// a hard line break when a line ends with the configured break. Indented
+    /// code blocks are found by their placeholders, which are removed
+    fn trim_trailing_whitespace(&self, s: &str) -> String {
+        let br = self.options.br.as_str();
+        let keeps_break = !br.is_empty() && br.trim().is_empty();
+        let mut fence: Option<(char, usize)> = None;
+        let mut in_indented_code = false;
+
+        s.split('\n')
+            .map(|line| {
+                if in_indented_code || line.contains(CODE_BLOCK_START) {
+                    in_indented_code = !line.contains(CODE_BLOCK_END);
+                    return line.replace([CODE_BLOCK_START, CODE_BLOCK_END], "");
+                }
+                // Fences may be indented inside list items, and close on a run of
+                // the same character at least as long as the one that opened them
+                let marker = line.trim_start();
+                let run = fence_run(marker);
+                if let Some((open_char, open_length)) = fence {
+                    if run.map_or(false, |(c, length)| {
+                        c == open_char
+                            && length >= open_length
+                            && marker[length..].trim().is_empty()
+                    }) {
+                        fence = None;
+                    }
+                    return line.to_string();
+                }
+                if run.is_some() {
+                    fence = run;
+                    return line.to_string();
+                }
+
+                let content = line.trim_end();
+                if keeps_break && !content.is_empty() && line.ends_with(br) {
+                    format!("{}{}", content, br)
+                } else {
+                    content.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
     /// Collapses sequences of 3+ newlines down to 2 newlines (representing 1 blank line)
@@ -322,13 +1221,21 @@ impl Turndown {
 
     /// Escapes Markdown special characters
     pub fn escape(&self, string: &str) -> String {
-        let mut result = string.to_string();
-        for (pattern, replacement) in &self.escape_patterns {
-            result = pattern
-                .replace_all(&result, replacement.as_str())
-                .to_string();
-        }
-        result
+        escape_text(string)
+    }
+
+    /// Wraps bare URLs in escaped text as autolinks, undoing their escaping
+    fn autolink(&self, escaped: &str) -> String {
+        self.bare_url_pattern
+            .replace_all(escaped, |caps: &regex::Captures| {
+                let url = unescape_url(&caps[0]);
+                let (url, rest) = url.split_at(autolink_url_length(&url));
+                if url.len() <= "https://".len() {
+                    return caps[0].to_string();
+                }
+                format!("<{}>{}", url, rest)
+            })
+            .to_string()
     }
 
     /// Joins two strings with appropriate newlines
@@ -352,10 +1259,28 @@ impl Turndown {
     }
 
     /// Adds a custom rule
+    ///
+    /// Rules are checked first to last, and each added rule is placed ahead of
+    /// every existing one, so the most recently added rule matching a node
+    /// takes precedence over earlier custom rules and the built-in rules.
     pub fn add_rule(&mut self, key: String, rule: Rule) {
         self.rules.add(key, rule);
     }
 
+    /// Adds several custom rules at once, ahead of every existing rule
+    ///
+    /// Unlike repeated `add_rule` calls, the rules keep the order they are given
+    /// in, so the first listed rule matching a node takes precedence.
+    pub fn add_rules(&mut self, rules: Vec<(String, Rule)>) {
+        self.rules.add_all(rules);
+    }
+
+    /// Sets the rule used for nodes that no other rule, kept or removed filter
+    /// matches. Its filter is never checked.
+    pub fn set_default_rule(&mut self, rule: Rule) {
+        self.rules.set_default(rule);
+    }
+
     /// Keeps nodes matching a filter as HTML
     pub fn keep(&mut self, filter: RuleFilter) {
         self.rules.keep(filter);
@@ -365,6 +1290,576 @@ impl Turndown {
     pub fn remove(&mut self, filter: RuleFilter) {
         self.rules.remove(filter);
     }
+
+    /// Sets a function that can mutate the parsed tree before it is converted
+    pub fn set_preprocessor(&mut self, f: Preprocessor) {
+        self.preprocessor = Some(f);
+    }
+
+    /// Sets a function that transforms the Markdown at the end of every conversion
+    pub fn set_post_processor(&mut self, f: PostProcessor) {
+        self.post_processor = Some(f);
+    }
+}
+
+/// Gets the character and length of the code fence a line starts with, a run
+/// of three or more backticks or tildes
+fn fence_run(line: &str) -> Option<(char, usize)> {
+    let c = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = line.len() - line.trim_start_matches(c).len();
+    (length >= 3).then_some((c, length))
+}
+
+/// Placeholder for an empty spacer block, a noncharacter that never occurs in
+/// converted text
+const BLOCK_SPACER: char = '\u{FDD0}';
+
+/// Placeholders around an indented code block, whose lines keep their trailing
+/// whitespace
+pub(crate) const CODE_BLOCK_START: char = '\u{FDD5}';
+pub(crate) const CODE_BLOCK_END: char = '\u{FDD6}';
+
+/// Replaces each run of spacer placeholders between two blocks with a single
+/// extra blank line, dropping those at the start or end of the document
+fn expand_block_spacers(markdown: &str) -> String {
+    let run = format!("{0}\n\n{0}", BLOCK_SPACER);
+    let mut expanded = markdown.to_string();
+    while expanded.contains(&run) {
+        expanded = expanded.replace(&run, &BLOCK_SPACER.to_string());
+    }
+
+    expanded
+        .replace(&format!("\n\n{}\n\n", BLOCK_SPACER), "\n\n\n")
+        .replace(BLOCK_SPACER, "")
+}
+
+/// Pairs each <label for> with the form control it names, giving the control
+/// the label text and marking the label so it is not rendered a second time
+fn resolve_form_labels(root: &mut Node) {
+    let mut labels = HashMap::new();
+    let mut stack: Vec<&Node> = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.node_name == "LABEL" {
+            if let Some(id) = node.get_attribute("for") {
+                let is_control = root.find_by_id(&id).map_or(false, |control| {
+                    matches!(control.node_name.as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+                });
+                let text = node
+                    .text_content()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let text = text.trim_end_matches(':').trim_end();
+                if is_control && !text.is_empty() && !labels.contains_key(&id) {
+                    labels.insert(id, text.to_string());
+                }
+            }
+        }
+        stack.extend(node.children.iter().rev());
+    }
+    if labels.is_empty() {
+        return;
+    }
+
+    let mut labelled = HashSet::new();
+    let mut stack: Vec<&mut Node> = vec![root];
+    while let Some(node) = stack.pop() {
+        match node.node_name.as_str() {
+            "INPUT" | "TEXTAREA" | "SELECT" => {
+                let id = node.get_attribute("id");
+                // Only the first element with a duplicated id is the labelled one
+                if let Some(id) = id.filter(|id| labels.contains_key(id)) {
+                    if labelled.insert(id.clone()) {
+                        node.set_attribute("data-form-label", &labels[&id]);
+                    }
+                }
+            }
+            "LABEL"
+                if node
+                    .get_attribute("for")
+                    .map_or(false, |id| labels.contains_key(&id)) =>
+            {
+                node.set_attribute("data-merged-label", "true");
+            }
+            _ => {}
+        }
+        stack.extend(node.children.iter_mut().rev());
+    }
+}
+
+/// Marks each <cite> directly inside a <blockquote> as the attribution of the quote
+fn mark_quote_attributions(root: &mut Node) {
+    let mut stack: Vec<&mut Node> = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.node_name == "BLOCKQUOTE" {
+            for child in node.children.iter_mut() {
+                if child.node_name == "CITE" {
+                    child.set_attribute("data-quote-attribution", "true");
+                }
+            }
+        }
+        stack.extend(node.children.iter_mut());
+    }
+}
+
+/// A list marker typed at the start of a list item's text
+enum ManualMarker {
+    Number(usize),
+    Bullet(char),
+}
+
+/// Gets the number the first item of a list is rendered with
+fn list_start(list: &Node) -> usize {
+    if list.node_name != "OL" {
+        return 1;
+    }
+    list.get_attribute("start")
+        .and_then(|start| start.trim().parse::<usize>().ok())
+        .unwrap_or(1)
+}
+
+/// Gets the number a list item with a value attribute is rendered with
+fn list_item_value(item: &Node) -> Option<usize> {
+    item.get_attribute("value")
+        .and_then(|value| value.trim().parse::<usize>().ok())
+}
+
+/// Strips the markers typed into the items of lists where they duplicate the
+/// generated ones: ordered lists whose typed numbers are the numbers the items
+/// are rendered with, following the list's start and the items' values, and
+/// bullet lists whose items all start with the same bullet. Lists where any
+/// item lacks a matching marker are left alone.
+fn dedupe_manual_list_markers(root: &mut Node, skip_spacers: bool) {
+    let mut stack: Vec<&mut Node> = vec![root];
+    while let Some(node) = stack.pop() {
+        let is_ordered = node.node_name == "OL";
+        if is_ordered || matches!(node.node_name.as_str(), "UL" | "MENU") {
+            let mut next_number = list_start(node);
+            let mut items: Vec<&mut Node> = node
+                .children
+                .iter_mut()
+                .filter(|child| child.node_name == "LI" && !(skip_spacers && child.is_spacer()))
+                .collect();
+
+            let numbers: Vec<usize> = items
+                .iter()
+                .map(|item| {
+                    let number = list_item_value(item).unwrap_or(next_number);
+                    next_number = number.saturating_add(1);
+                    number
+                })
+                .collect();
+            let markers: Vec<Option<(ManualMarker, usize)>> = items
+                .iter_mut()
+                .map(|item| leading_text(item).and_then(|text| manual_marker(&text.node_value)))
+                .collect();
+            let first_bullet = markers.first().and_then(|marker| match marker {
+                Some((ManualMarker::Bullet(bullet), _)) => Some(*bullet),
+                _ => None,
+            });
+            let is_redundant = !markers.is_empty()
+                && markers
+                    .iter()
+                    .zip(&numbers)
+                    .all(|(marker, rendered)| match marker {
+                        Some((ManualMarker::Number(number), _)) => is_ordered && number == rendered,
+                        Some((ManualMarker::Bullet(bullet), _)) => {
+                            !is_ordered && Some(*bullet) == first_bullet
+                        }
+                        None => false,
+                    });
+
+            if is_redundant {
+                for (item, marker) in items.iter_mut().zip(markers) {
+                    if let (Some(text), Some((_, length))) = (leading_text(item), marker) {
+                        text.node_value.replace_range(..length, "");
+                    }
+                }
+            }
+        }
+        stack.extend(node.children.iter_mut());
+    }
+}
+
+/// Merges links that repeat the link right before them, separated by whitespace
+/// at most. A link with the same URL, text and images is dropped, and a text link
+/// following an image-only link to the same URL is moved into it
+fn merge_adjacent_links(root: &mut Node) {
+    let mut stack: Vec<&mut Node> = vec![root];
+    while let Some(node) = stack.pop() {
+        let mut index = 0;
+        while index < node.children.len() {
+            let Some(href) = link_href(&node.children[index]) else {
+                index += 1;
+                continue;
+            };
+            let next = (index + 1..node.children.len()).find(|&next| {
+                let child = &node.children[next];
+                child.node_type != NodeType::Text || !child.node_value.trim().is_empty()
+            });
+            let Some(next) = next
+                .filter(|&next| link_href(&node.children[next]).as_deref() == Some(href.as_str()))
+            else {
+                index += 1;
+                continue;
+            };
+
+            let first = link_signature(&node.children[index]);
+            let second = link_signature(&node.children[next]);
+            let is_duplicate = first == second;
+            let is_captioned_image = first.0.is_empty()
+                && !first.1.is_empty()
+                && !second.0.is_empty()
+                && second.1.is_empty();
+            if !is_duplicate && !is_captioned_image {
+                index += 1;
+                continue;
+            }
+
+            let removed: Vec<Node> = node.children.drain(index + 1..=next).collect();
+            if let Some(text_link) = removed.into_iter().last().filter(|_| is_captioned_image) {
+                let link = &mut node.children[index];
+                link.children.push(Node::new_text(" "));
+                link.children.extend(text_link.children);
+            }
+        }
+        stack.extend(node.children.iter_mut());
+    }
+}
+
+/// Merges emphasis elements of the same kind that directly follow each other,
+/// whose delimiters would otherwise run together as in `_a__b_`
+fn merge_adjacent_emphasis(root: &mut Node) {
+    let kind = |node: &Node| match node.node_name.as_str() {
+        "EM" | "I" => Some("emphasis"),
+        "STRONG" | "B" => Some("strong"),
+        _ => None,
+    };
+
+    let mut stack: Vec<&mut Node> = vec![root];
+    while let Some(node) = stack.pop() {
+        let mut index = 1;
+        while index < node.children.len() {
+            let previous = kind(&node.children[index - 1]);
+            if previous.is_some() && previous == kind(&node.children[index]) {
+                let next = node.children.remove(index);
+                node.children[index - 1].children.extend(next.children);
+            } else {
+                index += 1;
+            }
+        }
+        stack.extend(node.children.iter_mut());
+    }
+}
+
+/// Joins text that is split up without any visible effect: <wbr> elements are
+/// dropped, <span> elements holding only text are unwrapped, and zero-width
+/// characters between two ASCII characters are removed
+fn reassemble_split_text(root: &mut Node) {
+    let mut stack: Vec<&mut Node> = vec![root];
+    while let Some(node) = stack.pop() {
+        let is_split = |child: &Node| {
+            child.node_name == "WBR"
+                || (child.node_name == "SPAN"
+                    && child
+                        .children
+                        .iter()
+                        .all(|text| text.node_type == NodeType::Text))
+        };
+        if node.children.iter().any(is_split) {
+            let mut children: Vec<Node> = Vec::with_capacity(node.children.len());
+            for child in std::mem::take(&mut node.children) {
+                let parts = if is_split(&child) {
+                    child.children
+                } else {
+                    vec![child]
+                };
+                for part in parts {
+                    match children.last_mut() {
+                        Some(last)
+                            if last.node_type == NodeType::Text
+                                && part.node_type == NodeType::Text
+                                && last.is_code == part.is_code =>
+                        {
+                            last.node_value.push_str(&part.node_value);
+                        }
+                        _ => children.push(part),
+                    }
+                }
+            }
+            node.children = children;
+        }
+
+        for child in node.children.iter_mut() {
+            if child.node_type == NodeType::Text {
+                child.node_value = strip_joining_zero_width(&child.node_value);
+            }
+        }
+        stack.extend(node.children.iter_mut());
+    }
+}
+
+/// Removes zero-width characters sitting between two visible ASCII characters,
+/// leaving those that join emoji sequences
+fn strip_joining_zero_width(text: &str) -> String {
+    let is_zero_width = |c: char| {
+        matches!(
+            c,
+            '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}'
+        )
+    };
+    if !text.contains(is_zero_width) {
+        return text.to_string();
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    for (index, &ch) in chars.iter().enumerate() {
+        if is_zero_width(ch) {
+            let before = chars[..index].iter().rev().find(|c| !is_zero_width(**c));
+            let after = chars[index + 1..].iter().find(|c| !is_zero_width(**c));
+            let is_ascii = |c: Option<&char>| c.map_or(false, |c| c.is_ascii_graphic());
+            if is_ascii(before) && is_ascii(after) {
+                continue;
+            }
+        }
+        result.push(ch);
+    }
+    result
+}
+
+/// Gets the trimmed href of a link element
+fn link_href(node: &Node) -> Option<String> {
+    if node.node_type != NodeType::Element || node.node_name != "A" {
+        return None;
+    }
+    node.get_attribute("href")
+        .map(|href| href.trim().to_string())
+}
+
+/// Gets the whitespace-collapsed text of a link and the sources of its images
+fn link_signature(link: &Node) -> (String, Vec<String>) {
+    let text = link
+        .text_content()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut images = Vec::new();
+    let mut stack = vec![link];
+    while let Some(node) = stack.pop() {
+        if node.node_name == "IMG" {
+            images.push(node.get_attribute("src").unwrap_or_default());
+        }
+        stack.extend(node.children.iter().rev());
+    }
+    (text, images)
+}
+
+/// Gets the first text node with visible content in an element, looking into
+/// its first elements
+fn leading_text(node: &mut Node) -> Option<&mut Node> {
+    let child = node
+        .children
+        .iter_mut()
+        .find(|child| child.node_type != NodeType::Text || !child.node_value.trim().is_empty())?;
+    match child.node_type {
+        NodeType::Text => Some(child),
+        NodeType::Element if !child.is_void() => leading_text(child),
+        _ => None,
+    }
+}
+
+/// Parses a marker such as `1.`, `2)`, `-` or `•` followed by whitespace at the
+/// start of text, with the length of the marker and its surrounding whitespace
+fn manual_marker(text: &str) -> Option<(ManualMarker, usize)> {
+    let trimmed = text.trim_start();
+    let digits = trimmed.len()
+        - trimmed
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .len();
+
+    let (marker, rest) = if digits > 0 && digits <= 9 {
+        let rest = trimmed[digits..].strip_prefix(['.', ')'])?;
+        (ManualMarker::Number(trimmed[..digits].parse().ok()?), rest)
+    } else {
+        let bullet = trimmed
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '-' | '*' | '+' | '•'))?;
+        (ManualMarker::Bullet(bullet), &trimmed[bullet.len_utf8()..])
+    };
+
+    // A marker is followed by whitespace, which its text or an element comes after
+    let content = rest.trim_start();
+    if content.len() == rest.len() {
+        return None;
+    }
+    Some((marker, text.len() - content.len()))
+}
+
+/// Finds the BODY element of a parsed document
+fn find_body(node: &Node) -> Option<&Node> {
+    if node.node_name == "BODY" {
+        return Some(node);
+    }
+    node.children
+        .iter()
+        .filter(|child| matches!(child.node_type, NodeType::Document | NodeType::Element))
+        .find_map(find_body)
+}
+
+/// Checks if the line break at `index` only has block elements (or nothing) around it,
+/// looking past whitespace and other line breaks
+fn is_stray_line_break(siblings: &[&Node], index: usize) -> bool {
+    let is_filler = |node: &&&Node| {
+        node.node_name == "BR"
+            || (node.node_type == NodeType::Text && node.node_value.trim().is_empty())
+            || node.node_type == NodeType::Comment
+    };
+
+    let previous = siblings[..index]
+        .iter()
+        .rev()
+        .find(|node| !is_filler(node))
+        .copied();
+    let next = siblings[index + 1..]
+        .iter()
+        .find(|node| !is_filler(node))
+        .copied();
+
+    let is_block_or_edge = |node: Option<&Node>| node.map_or(true, |node| node.is_block());
+
+    (previous.is_some() || next.is_some()) && is_block_or_edge(previous) && is_block_or_edge(next)
+}
+
+/// Checks if the node at `index` is an <article> or <section> directly following
+/// another, looking past whitespace and comments
+fn follows_section(siblings: &[&Node], index: usize) -> bool {
+    let is_section = |node: &Node| matches!(node.node_name.as_str(), "ARTICLE" | "SECTION");
+    if !is_section(siblings[index]) {
+        return false;
+    }
+
+    siblings[..index]
+        .iter()
+        .rev()
+        .find(|node| {
+            !(node.node_type == NodeType::Comment
+                || (node.node_type == NodeType::Text && node.node_value.trim().is_empty()))
+        })
+        .map_or(false, |node| is_section(node))
+}
+
+/// Merges adjacent text nodes into one so that escaping sees whole words
+fn merge_text_runs(children: Vec<&Node>) -> Vec<Cow<'_, Node>> {
+    let mut merged: Vec<Cow<Node>> = Vec::with_capacity(children.len());
+    for child in children {
+        if let Some(last) = merged.last_mut() {
+            let mergeable = |node: &Node| node.node_type == NodeType::Text;
+            if mergeable(last) && mergeable(child) && last.is_code == child.is_code {
+                last.to_mut().node_value.push_str(&child.node_value);
+                continue;
+            }
+        }
+        merged.push(Cow::Borrowed(child));
+    }
+    merged
+}
+
+/// Joins runs of KBD elements separated by `+`, such as
+/// `<kbd>Ctrl</kbd>+<kbd>C</kbd>`, into a single KBD element
+fn merge_key_combos(children: Vec<Cow<'_, Node>>) -> Vec<Cow<'_, Node>> {
+    let is_kbd = |node: &Node| node.node_name == "KBD";
+    let is_plus = |node: &Node| node.node_type == NodeType::Text && node.node_value.trim() == "+";
+
+    let mut merged: Vec<Cow<Node>> = Vec::with_capacity(children.len());
+    let mut combo: Vec<Cow<Node>> = Vec::new();
+    let mut index = 0;
+    while index < children.len() {
+        let joins_next = is_kbd(&children[index])
+            && children.get(index + 1).map_or(false, |next| is_plus(next))
+            && children.get(index + 2).map_or(false, |next| is_kbd(next));
+
+        if joins_next {
+            combo.push(children[index].clone());
+            combo.push(Cow::Owned(Node::new_text("+")));
+            index += 2;
+            continue;
+        }
+
+        if combo.is_empty() {
+            merged.push(children[index].clone());
+        } else {
+            combo.push(children[index].clone());
+            let mut keys = Node::new_element("kbd");
+            keys.children = combo.drain(..).map(Cow::into_owned).collect();
+            merged.push(Cow::Owned(keys));
+        }
+        index += 1;
+    }
+    merged
+}
+
+/// Removes the escaping added to characters within a URL
+fn unescape_url(url: &str) -> String {
+    let mut result = String::with_capacity(url.len());
+    let mut chars = url.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match chars.peek() {
+            Some(&next) if ch == '\\' && "\\*`[]_".contains(next) => {
+                result.push(next);
+                chars.next();
+            }
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+/// Gets the length of a URL without trailing punctuation that belongs to the
+/// surrounding sentence, keeping closing parentheses that balance the URL
+fn autolink_url_length(url: &str) -> usize {
+    let mut end = url.len();
+    while let Some(last) = url[..end].chars().last() {
+        let candidate = &url[..end];
+        let unbalanced =
+            last == ')' && candidate.matches(')').count() > candidate.matches('(').count();
+        if !(unbalanced || ".,:;!?'\"".contains(last)) {
+            break;
+        }
+        end -= last.len_utf8();
+    }
+    end
+}
+
+/// Checks if an emphasis element sits inside a word, touching letters of the
+/// text before or after it, e.g. `un<em>be</em>lievable`
+fn is_intraword(siblings: &[&Node], index: usize) -> bool {
+    let node = siblings[index];
+    if !matches!(node.node_name.as_str(), "EM" | "I" | "STRONG" | "B" | "U") {
+        return false;
+    }
+
+    let text = node.text_content();
+    let is_word_text = |sibling: Option<&&Node>, at_end: bool| {
+        sibling.map_or(false, |sibling| {
+            let value = &sibling.node_value;
+            let ch = if at_end {
+                value.chars().last()
+            } else {
+                value.chars().next()
+            };
+            sibling.node_type == NodeType::Text && ch.map_or(false, char::is_alphanumeric)
+        })
+    };
+
+    let touches_previous = index > 0
+        && is_word_text(siblings.get(index - 1), true)
+        && !text.starts_with(char::is_whitespace);
+    let touches_next =
+        is_word_text(siblings.get(index + 1), false) && !text.ends_with(char::is_whitespace);
+
+    !text.trim().is_empty() && (touches_previous || touches_next)
 }
 
 impl Default for Turndown {
@@ -399,6 +1894,183 @@ mod tests {
         assert_eq!(result, "");
     }
 
+    #[test]
+    fn test_post_processor() {
+        let mut turndown = Turndown::new();
+        turndown.set_post_processor(Box::new(|markdown| format!("---\n{}", markdown)));
+        assert_eq!(turndown.convert("<p>Hello</p>"), "---\nHello");
+    }
+
+    #[test]
+    fn test_preprocessor() {
+        fn strip_images(node: &mut Node) {
+            node.children.retain(|child| child.node_name != "IMG");
+            for child in &mut node.children {
+                strip_images(child);
+            }
+        }
+
+        let mut turndown = Turndown::new();
+        turndown.set_preprocessor(Box::new(strip_images));
+        let html = r#"<p>Before <img src="a.png" alt="A">after</p><img src="b.png" alt="B">"#;
+        assert_eq!(turndown.convert(html), "Before after");
+    }
+
+    #[test]
+    fn test_split_text_nodes_are_escaped_together() {
+        fn split_text(node: &mut Node) {
+            for child in &mut node.children {
+                split_text(child);
+            }
+            if node.node_name == "P" {
+                let text = node.text_content();
+                let (first, rest) = text.split_at(text.find(['.', '_']).unwrap());
+                node.children = vec![Node::new_text(first), Node::new_text(rest)];
+            }
+        }
+
+        let mut turndown = Turndown::new();
+        turndown.set_preprocessor(Box::new(split_text));
+        assert_eq!(
+            turndown.convert("<p>1. Not a list</p><p>snake_case</p>"),
+            "1\\. Not a list\n\nsnake\\_case"
+        );
+    }
+
+    #[test]
+    fn test_convert_with_stats() {
+        let turndown = Turndown::new();
+        let html = r#"
+            <h1>Title</h1>
+            <p><a href="https://a.example">One</a> and <a href="https://b.example">Two</a></p>
+            <img src="https://example.com/photo.png" alt="Photo">
+            <img src="https://example.com/pixel.gif" width="1" height="1">
+        "#;
+        let (markdown, stats) = turndown.convert_with_stats(html);
+        assert_eq!(markdown, turndown.convert(html));
+        assert_eq!(
+            stats,
+            ConvertStats {
+                links: 2,
+                images: 1,
+                headings: 1,
+                ..Default::default()
+            }
+        );
+
+        // Only images dropped as tracking count as such, and emoji written as
+        // their alt text are not images
+        let turndown = Turndown::with_options(TurndownOptions {
+            strip_tracking_images: true,
+            strip_images_without_alt: true,
+            ..Default::default()
+        });
+        let html = r#"
+            <img src="https://mail.example/track/open.gif?id=7" alt="">
+            <img src="https://example.com/banner.png">
+            <img class="emoji" src="https://example.com/smile.png" alt="🙂">
+            <img src="https://example.com/photo.png" alt="Photo">
+        "#;
+        let (markdown, stats) = turndown.convert_with_stats(html);
+        assert_eq!(markdown, "🙂 ![Photo](https://example.com/photo.png)");
+        assert_eq!(
+            stats,
+            ConvertStats {
+                images: 1,
+                stripped_tracking_images: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_convert_sections() {
+        let turndown = Turndown::new();
+        let html = "<h2>One</h2><p>First</p><hr><h2>Two</h2><p>Second</p><hr><p>Third</p>";
+        assert_eq!(
+            turndown.convert_sections(html),
+            vec!["## One\n\nFirst", "## Two\n\nSecond", "Third"]
+        );
+
+        let mut turndown = Turndown::new();
+        turndown.set_post_processor(Box::new(|markdown| format!("{}!", markdown)));
+        assert_eq!(
+            turndown.convert_sections("<p>One</p><hr><p>Two</p>"),
+            vec!["One!", "Two!"]
+        );
+    }
+
+    #[test]
+    fn test_trailing_whitespace() {
+        let turndown = Turndown::new();
+        assert_eq!(turndown.convert("<p>Hello<br></p>"), "Hello  ");
+        assert_eq!(
+            turndown.convert("<p>one<br>two</p><p>three </p>"),
+            "one  \ntwo\n\nthree"
+        );
+        assert_eq!(
+            turndown.convert("<ul><li>item\n<ul><li>nested</li></ul></li></ul>"),
+            "* item\n  * nested"
+        );
+        assert_eq!(
+            turndown.convert("<pre><code>keep   \nthis</code></pre>"),
+            "```\nkeep   \nthis\n```"
+        );
+
+        let indented = Turndown::with_options(TurndownOptions {
+            code_block_style: CodeBlockStyle::Indented,
+            ..Default::default()
+        });
+        assert_eq!(
+            indented.convert("<pre>line one   \nline two  </pre><p>after </p>"),
+            "    line one   \n    line two  \n\nafter"
+        );
+        assert_eq!(
+            indented.convert("<blockquote><pre>quoted  </pre></blockquote>"),
+            ">     quoted  "
+        );
+
+        // Longer and indented fences keep the whitespace inside them too
+        let raw = Turndown::with_options(TurndownOptions {
+            raw_markdown_attribute: Some("data-md-raw".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            raw.convert(
+                "<ul><li>Item<div data-md-raw>````\n```  \nkeep  \n````</div></li></ul><p>after </p>"
+            ),
+            "* Item\n\n  ````\n  ```  \n  keep  \n  ````\n\nafter"
+        );
+        assert_eq!(
+            turndown.convert("<ul><li>Item<pre><code>keep  \nthis</code></pre></li></ul>"),
+            "* Item\n\n  ```\n  keep  \n  this\n  ```"
+        );
+    }
+
+    #[test]
+    fn test_autolink_url_length() {
+        assert_eq!(autolink_url_length("https://a.io/x."), 14);
+        assert_eq!(autolink_url_length("https://a.io/x_(y))"), 18);
+        assert_eq!(autolink_url_length("https://a.io/?q=1"), 17);
+    }
+
+    #[test]
+    fn test_tracking_heuristics() {
+        let heuristics = TrackingHeuristics {
+            max_query_length: Some(64),
+            size_hint_in_url: true,
+            query_keys: vec!["mid".to_string(), "eid".to_string()],
+        };
+
+        let long_query = format!("https://mail.example.com/o.png?d={}", "a1b2c3".repeat(20));
+        assert!(heuristics.matches(&long_query));
+        assert!(heuristics.matches("https://cdn.example.com/i.png?w=1&h=1"));
+        assert!(heuristics.matches("https://cdn.example.com/i.png?MID=42"));
+        assert!(!heuristics.matches("https://cdn.example.com/photo.png?w=640&h=480"));
+        assert!(!heuristics.matches("https://cdn.example.com/photo.png"));
+        assert!(!TrackingHeuristics::default().matches(&long_query));
+    }
+
     #[test]
     fn test_simple_paragraph() {
         let turndown = Turndown::new();
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use unicode_width::UnicodeWidthStr;
+
 /// Collapses whitespace according to HTML rules (adapted from the DOM-based JavaScript implementation)
 /// - Replaces sequences of spaces, tabs, newlines, and carriage returns with a single space
 /// - Preserves blank lines (double newlines with optional whitespace between)
@@ -24,7 +27,7 @@ pub fn collapse_whitespace(s: &str) -> String {
                 i = j + 1;
             } else {
                 // Single newline - collapse to space (following JavaScript behavior)
-                if !result.ends_with(' ') && !result.is_empty() {
+                if !result.ends_with(' ') && !result.ends_with('\n') {
                     result.push(' ');
                 }
                 i += 1;
@@ -49,8 +52,8 @@ pub fn collapse_whitespace(s: &str) -> String {
         }
     }
 
-    if result.trim().is_empty() {
-        String::new()
+    if result.trim().is_empty() && !result.is_empty() {
+        " ".to_string()
     } else {
         result
     }
@@ -75,6 +78,22 @@ pub fn trim_newlines(s: &str) -> &str {
     trim_trailing_newlines(trim_leading_newlines(s))
 }
 
+/// Gets the number of terminal columns text takes up, counting wide characters
+/// such as CJK ideographs as two columns and combining marks as none
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Shortens text longer than `max_chars` characters to that many, ending it with an
+/// ellipsis. Characters are never split, though combining marks may be parted from
+/// the character before them
+pub fn truncate_text(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_string(),
+    }
+}
+
 /// Repeats a character n times
 pub fn repeat(ch: char, count: usize) -> String {
     (0..count).map(|_| ch).collect()
@@ -133,7 +152,6 @@ pub const BLOCK_ELEMENTS: &[&str] = &[
     "NOFRAMES",
     "NOSCRIPT",
     "OL",
-    "OUTPUT",
     "P",
     "PRE",
     "SECTION",
@@ -213,9 +231,355 @@ pub fn is_tracking_image(
     false
 }
 
+/// Parses an inline style attribute into lowercased property/value declarations,
+/// ignoring any `!important` flags
+pub fn parse_style(style: &str) -> HashMap<String, String> {
+    style
+        .split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let property = property.trim().to_lowercase();
+            let value = value
+                .trim()
+                .trim_end_matches("!important")
+                .trim()
+                .to_lowercase();
+            (!property.is_empty()).then_some((property, value))
+        })
+        .collect()
+}
+
+/// Replaces typographic punctuation (curly quotes, dashes, ellipses) with ASCII
+pub fn typography_to_ascii(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{00AB}' | '\u{00BB}' => {
+                result.push('"')
+            }
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => result.push('\''),
+            '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' => result.push('-'),
+            '\u{2014}' => result.push_str("--"),
+            '\u{2026}' => result.push_str("..."),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+/// Decodes percent-encoded sequences, leaving malformed sequences untouched
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Extracts the destination embedded in a redirect link's query parameters
+pub fn unwrap_redirect(href: &str, params: &[String]) -> Option<String> {
+    let (_, query) = href.split_once('?')?;
+    let query = query.split('#').next().unwrap_or_default();
+
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if !params.iter().any(|param| param.eq_ignore_ascii_case(key)) {
+            return None;
+        }
+
+        let url = percent_decode(value);
+        let lower = url.to_lowercase();
+        (lower.starts_with("http://") || lower.starts_with("https://")).then_some(url)
+    })
+}
+
+/// Escapes Markdown syntax in plain text: characters that start inline markup
+/// anywhere, and block markers such as `#`, `>` or `1.` at the start. Decoded
+/// text such as `<div>` or `&amp;` is escaped so it does not read as HTML again
+pub fn escape_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let equals = text.len() - text.trim_start_matches('=').len();
+    let hashes = text.len() - text.trim_start_matches('#').len();
+
+    // Block markers only count at the start, so only the first one is escaped
+    let rest = if let Some(rest) = text.strip_prefix('-') {
+        result.push_str("\\-");
+        rest
+    } else if let Some(rest) = text.strip_prefix("+ ") {
+        result.push_str("\\+ ");
+        rest
+    } else if equals > 0 {
+        result.push('\\');
+        result.push_str(&text[..equals]);
+        &text[equals..]
+    } else if (1..=6).contains(&hashes)
+        && (text.len() == hashes || text[hashes..].starts_with([' ', '\t']))
+    {
+        // An ATX heading opens with at most six hashes, ended by a space, a tab or
+        // the end of the line, so `#hashtag` and `####### x` stay as they are
+        result.push('\\');
+        result.push_str(&text[..hashes]);
+        &text[hashes..]
+    } else if let Some(rest) = text.strip_prefix("~~~") {
+        result.push_str("\\~~~");
+        rest
+    } else if let Some(rest) = text.strip_prefix('>') {
+        result.push_str("\\>");
+        rest
+    } else if digits > 0 && text[digits..].starts_with(". ") {
+        result.push_str(&text[..digits]);
+        result.push_str("\\. ");
+        &text[digits + 2..]
+    } else {
+        text
+    };
+
+    for (i, ch) in rest.char_indices() {
+        let follows = &rest[i + ch.len_utf8()..];
+        let is_markup = match ch {
+            '\\' | '*' | '`' | '[' | ']' | '_' => true,
+            '<' => follows.starts_with(|c: char| c.is_ascii_alphabetic() || "/!?".contains(c)),
+            '&' => starts_entity(follows),
+            _ => false,
+        };
+        if is_markup {
+            result.push('\\');
+        }
+        result.push(ch);
+    }
+    result
+}
+
+/// Checks if text following an `&` completes a named or numeric character reference
+fn starts_entity(text: &str) -> bool {
+    let (reference, radix) =
+        if let Some(hex) = text.strip_prefix("#x").or_else(|| text.strip_prefix("#X")) {
+            (hex, Some(16))
+        } else if let Some(decimal) = text.strip_prefix('#') {
+            (decimal, Some(10))
+        } else {
+            (text, None)
+        };
+
+    let rest = reference.trim_start_matches(|c: char| match radix {
+        Some(radix) => c.is_digit(radix),
+        None => c.is_ascii_alphanumeric(),
+    });
+    rest.len() < reference.len() && rest.starts_with(';')
+}
+
+/// Escapes text placed between the brackets of a link or image, such as alt
+/// text, where backslashes and brackets are the only significant characters
+pub fn escape_link_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '\\' | '[' | ']') {
+            result.push('\\');
+        }
+        result.push(ch);
+    }
+    result
+}
+
+/// Escapes the pipes in the Markdown content of a table cell. Pipes that are
+/// already escaped are left alone, except in code spans, where a backslash is
+/// literal but pipe tables still read `\\|` as an escaped pipe
+pub fn escape_table_cell(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for (segment, is_code) in split_code_spans(text) {
+        let mut backslashes = 0;
+        for ch in segment.chars() {
+            if ch == '|' && (is_code || backslashes % 2 == 0) {
+                result.push('\\');
+            }
+            backslashes = if ch == '\\' { backslashes + 1 } else { 0 };
+            result.push(ch);
+        }
+    }
+    result
+}
+
+/// Splits Markdown into runs of text and code spans, in order, flagging the
+/// code spans. A backtick run without a closing run of the same length is text
+pub(crate) fn split_code_spans(markdown: &str) -> Vec<(&str, bool)> {
+    let bytes = markdown.as_bytes();
+    let run_length = |at: usize| bytes[at..].iter().take_while(|&&b| b == b'`').count();
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let opening = run_length(i);
+                let mut j = i + opening;
+                let mut closing = None;
+                while j < bytes.len() {
+                    if bytes[j] == b'`' {
+                        let length = run_length(j);
+                        if length == opening {
+                            closing = Some(j + length);
+                            break;
+                        }
+                        j += length;
+                    } else {
+                        j += 1;
+                    }
+                }
+
+                match closing {
+                    Some(end) => {
+                        if start < i {
+                            segments.push((&markdown[start..i], false));
+                        }
+                        segments.push((&markdown[i..end], true));
+                        start = end;
+                        i = end;
+                    }
+                    None => i += opening,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    if start < markdown.len() {
+        segments.push((&markdown[start..], false));
+    }
+    segments
+}
+
+/// Checks if an image is an emoji shim based on its class or src
+pub fn is_emoji_image(
+    node: &crate::node::Node,
+    src: &str,
+    emoji_regex: Option<&regex::Regex>,
+) -> bool {
+    node.has_class("emoji") || emoji_regex.map_or(false, |regex| regex.is_match(src))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::node::Node;
+
+    #[test]
+    fn test_is_emoji_image() {
+        let regex = crate::TurndownOptions::default().emoji_image_regex;
+        let image = |class: &str| {
+            let mut node = Node::new_element("img");
+            node.set_attribute("class", class);
+            node
+        };
+
+        assert!(is_emoji_image(
+            &image("wp-smiley emoji"),
+            "",
+            regex.as_ref()
+        ));
+        assert!(!is_emoji_image(&image("no-emoji-here"), "", regex.as_ref()));
+        assert!(is_emoji_image(
+            &image(""),
+            "https://twemoji.maxcdn.com/v/latest/72x72/1f600.png",
+            regex.as_ref()
+        ));
+        assert!(!is_emoji_image(
+            &image(""),
+            "https://example.com/blog/emoji/launch-party.jpg",
+            regex.as_ref()
+        ));
+    }
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(
+            escape_text("a *b* [c] `d` _e_ \\f"),
+            "a \\*b\\* \\[c\\] \\`d\\` \\_e\\_ \\\\f"
+        );
+        assert_eq!(escape_text("- item"), "\\- item");
+        assert_eq!(escape_text("+ item"), "\\+ item");
+        assert_eq!(escape_text("== title"), "\\== title");
+        assert_eq!(escape_text("## title"), "\\## title");
+        assert_eq!(escape_text("####### title"), "####### title");
+        assert_eq!(escape_text("~~~ fence"), "\\~~~ fence");
+        assert_eq!(escape_text("> quote"), "\\> quote");
+        assert_eq!(escape_text("12. item"), "12\\. item");
+        assert_eq!(
+            escape_text("in - the # middle > 1. of text"),
+            "in - the # middle > 1. of text"
+        );
+        assert_eq!(escape_text("a <div> or </p>"), "a \\<div> or \\</p>");
+        assert_eq!(escape_text("3 < 4 & 5 <= 6"), "3 < 4 & 5 <= 6");
+        assert_eq!(
+            escape_text("&amp; &#169; &#x1F600; &x"),
+            "\\&amp; \\&#169; \\&#x1F600; &x"
+        );
+    }
+
+    #[test]
+    fn test_truncate_text() {
+        assert_eq!(truncate_text("short", 5), "short");
+        assert_eq!(truncate_text("a longer text", 8), "a longer…");
+        assert_eq!(truncate_text("a longer text", 2), "a…");
+        assert_eq!(truncate_text("日本語のテキスト", 3), "日本語…");
+        assert_eq!(truncate_text("🦀🦀🦀", 1), "🦀…");
+    }
+
+    #[test]
+    fn test_escape_text_heading_markers() {
+        assert_eq!(escape_text("# real"), "\\# real");
+        assert_eq!(escape_text("###\tTabbed"), "\\###\tTabbed");
+        assert_eq!(escape_text("##"), "\\##");
+        assert_eq!(escape_text("#hashtag"), "#hashtag");
+        assert_eq!(escape_text("####### x"), "####### x");
+    }
+
+    #[test]
+    fn test_escape_link_text() {
+        assert_eq!(escape_link_text("[draft] *v2*"), "\\[draft\\] *v2*");
+        assert_eq!(escape_link_text("C:\\Temp"), "C:\\\\Temp");
+    }
+
+    #[test]
+    fn test_escape_table_cell() {
+        assert_eq!(escape_table_cell("a | b"), "a \\| b");
+        assert_eq!(escape_table_cell("`x || y`"), "`x \\|\\| y`");
+        assert_eq!(escape_table_cell("a \\| b"), "a \\| b");
+        assert_eq!(escape_table_cell("a \\\\| b"), "a \\\\\\| b");
+        assert_eq!(escape_table_cell("`a \\| b` \\|"), "`a \\\\| b` \\|");
+    }
+
+    #[test]
+    fn test_split_code_spans() {
+        assert_eq!(
+            split_code_spans("a `b` c"),
+            vec![("a ", false), ("`b`", true), (" c", false)]
+        );
+        assert_eq!(
+            split_code_spans("``x ` y`` \\`z`"),
+            vec![("``x ` y``", true), (" \\`z`", false)]
+        );
+        assert_eq!(split_code_spans("``open"), vec![("``open", false)]);
+    }
 
     #[test]
     fn test_trim_leading_newlines() {
@@ -229,6 +593,14 @@ mod tests {
         assert_eq!(trim_trailing_newlines("test"), "test");
     }
 
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("Hello"), 5);
+        assert_eq!(display_width("你好世界"), 8);
+        assert_eq!(display_width("Café"), 4);
+        assert_eq!(display_width("e\u{301}"), 1);
+    }
+
     #[test]
     fn test_repeat() {
         assert_eq!(repeat('#', 3), "###");
@@ -255,6 +627,55 @@ mod tests {
         assert_eq!(clean_attribute(None), "");
     }
 
+    #[test]
+    fn test_parse_style() {
+        let style = parse_style("Display: none !important; max-height:0px;; color:#FFF");
+        assert_eq!(style.get("display").map(String::as_str), Some("none"));
+        assert_eq!(style.get("max-height").map(String::as_str), Some("0px"));
+        assert_eq!(style.get("color").map(String::as_str), Some("#fff"));
+        assert_eq!(style.len(), 3);
+    }
+
+    #[test]
+    fn test_typography_to_ascii() {
+        assert_eq!(
+            typography_to_ascii("\u{201C}Don\u{2019}t\u{201D} \u{2013} wait\u{2026}"),
+            "\"Don't\" - wait..."
+        );
+        assert_eq!(
+            typography_to_ascii("e\u{2011}mail \u{2014} done"),
+            "e-mail -- done"
+        );
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(
+            percent_decode("https%3A%2F%2Freal.com%2F"),
+            "https://real.com/"
+        );
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn test_unwrap_redirect() {
+        let params = vec!["u".to_string(), "url".to_string()];
+        assert_eq!(
+            unwrap_redirect(
+                "https://track.x/abc?id=1&u=https%3A%2F%2Freal.com%2Fa",
+                &params
+            ),
+            Some("https://real.com/a".to_string())
+        );
+        assert_eq!(
+            unwrap_redirect("https://track.x/abc?u=not-a-url", &params),
+            None
+        );
+        assert_eq!(unwrap_redirect("https://real.com/page", &params), None);
+    }
+
     #[test]
     fn test_collapse_whitespace_simple() {
         // Simple space should be preserved
@@ -284,4 +705,18 @@ mod tests {
         // Leading/trailing spaces should be preserved for inline spacing
         assert_eq!(collapse_whitespace("  text  "), " text ");
     }
+
+    #[test]
+    fn test_collapse_whitespace_only() {
+        // Whitespace between inline elements is kept as the space separating them
+        assert_eq!(collapse_whitespace(" \n\t "), " ");
+        assert_eq!(collapse_whitespace(""), "");
+    }
+
+    #[test]
+    fn test_collapse_whitespace_leading_newline() {
+        // Text starting on a new line after an inline element keeps its separating space
+        assert_eq!(collapse_whitespace("\n  for details"), " for details");
+        assert_eq!(collapse_whitespace("\n|\n"), " | ");
+    }
 }
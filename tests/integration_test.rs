@@ -48,14 +48,12 @@ fn collect_html_files(dir: &Path) -> Vec<std::path::PathBuf> {
     let mut html_files = Vec::new();
 
     if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_dir() {
-                    html_files.extend(collect_html_files(&path));
-                } else if path.extension().and_then(|ext| ext.to_str()) == Some("html") {
-                    html_files.push(path);
-                }
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                html_files.extend(collect_html_files(&path));
+            } else if path.extension().and_then(|ext| ext.to_str()) == Some("html") {
+                html_files.push(path);
             }
         }
     }
@@ -114,57 +112,7 @@ fn normalize_output(s: &str) -> String {
         last_was_blank = is_blank;
     }
 
-    // Normalize inline spacing: add spaces after links and inline elements
-    let result = collapsed.join("\n");
-    let result = normalize_inline_spacing(&result);
-    result
-}
-
-/// Normalizes inline spacing to handle missing spaces after links and inline elements
-fn normalize_inline_spacing(s: &str) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = s.chars().collect();
-    let mut i = 0;
-
-    while i < chars.len() {
-        if i + 1 < chars.len() {
-            // Handle missing space after link markdown: `)letter` -> `) letter`
-            if chars[i] == ')' && chars[i + 1].is_alphabetic() {
-                result.push(chars[i]);
-                result.push(' ');
-                i += 1;
-                continue;
-            }
-            // Handle missing space before pipe: `)| ` -> `) | `
-            if chars[i] == ')' && chars[i + 1] == '|' {
-                result.push(chars[i]);
-                result.push(' ');
-                i += 1;
-                continue;
-            }
-            // Handle missing space after pipe before link: `| [` -> should stay as is, but normalize `|[` to `| [`
-            if chars[i] == '|' && chars[i + 1] == '[' {
-                result.push(chars[i]);
-                result.push(' ');
-                i += 1;
-                continue;
-            }
-            // Handle missing space after numbers (like superscripts): `1W` -> `1 W`
-            if chars[i].is_numeric() && chars[i + 1].is_alphabetic() {
-                // Check if this looks like a superscript number followed by text
-                if i > 0 && chars[i - 1].is_whitespace() {
-                    result.push(chars[i]);
-                    result.push(' ');
-                    i += 1;
-                    continue;
-                }
-            }
-        }
-        result.push(chars[i]);
-        i += 1;
-    }
-
-    result
+    collapsed.join("\n")
 }
 
 /// Generates a simple diff output
@@ -235,6 +183,19 @@ fn test_all_fixtures() {
     }
 }
 
+/// Runs a fixture from the option fixtures directory with a configured turndown
+fn assert_option_fixture(name: &str, turndown: &Turndown) {
+    let test = TestCase {
+        name: name.to_string(),
+        html_path: format!("tests/option_fixtures/{}.html", name),
+        expected_path: format!("tests/option_fixtures/{}.md", name),
+    };
+
+    if let Err(err) = run_test_case(&test, turndown) {
+        panic!("Test: {}\n{}", test.name, err);
+    }
+}
+
 #[test]
 fn test_default_options() {
     let turndown = Turndown::new();
@@ -257,9 +218,11 @@ fn test_tracking_image_stripping() {
     use turndown::TurndownOptions;
 
     // Create options with tracking image stripping enabled
-    let mut options = TurndownOptions::default();
-    options.strip_tracking_images = true;
-    options.strip_images_without_alt = true;
+    let options = TurndownOptions {
+        strip_tracking_images: true,
+        strip_images_without_alt: true,
+        ..Default::default()
+    };
 
     let turndown = turndown::Turndown::with_options(options);
 
@@ -290,9 +253,11 @@ fn test_custom_tracking_regex() {
     use turndown::TurndownOptions;
 
     // Create options with custom tracking regex
-    let mut options = TurndownOptions::default();
-    options.strip_tracking_images = true;
-    options.tracking_image_regex = Some(Regex::new(r"foo\.php").unwrap());
+    let options = TurndownOptions {
+        strip_tracking_images: true,
+        tracking_image_regex: Some(Regex::new(r"foo\.php").unwrap()),
+        ..Default::default()
+    };
 
     let turndown = turndown::Turndown::with_options(options);
 
@@ -319,9 +284,11 @@ fn test_images_without_alt_stripping() {
     use turndown::TurndownOptions;
 
     // Create options with alt-less image stripping enabled
-    let mut options = TurndownOptions::default();
-    options.strip_tracking_images = true;
-    options.strip_images_without_alt = true;
+    let options = TurndownOptions {
+        strip_tracking_images: true,
+        strip_images_without_alt: true,
+        ..Default::default()
+    };
 
     let turndown = turndown::Turndown::with_options(options);
 
@@ -363,3 +330,1939 @@ fn test_tracking_stripping_disabled_by_default() {
     assert!(result.contains("track.php"));
     assert!(result.contains("image.png"));
 }
+
+#[test]
+fn test_document_wrappers_add_no_spacing() {
+    let turndown = Turndown::new();
+    let bare = turndown.convert("<p>a</p>");
+
+    assert_eq!(bare, "a");
+    assert_eq!(turndown.convert("<html><body><p>a</p></body></html>"), bare);
+    assert_eq!(
+        turndown.convert(
+            "<!DOCTYPE html>\n<html>\n<head></head>\n<body>\n<p>a</p>\n</body>\n</html>\n"
+        ),
+        bare
+    );
+}
+
+#[test]
+fn test_title_as_heading() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        title_as_heading: true,
+        ..Default::default()
+    };
+
+    let turndown = Turndown::with_options(options);
+    assert_option_fixture("title_as_heading", &turndown);
+    assert_eq!(
+        turndown.convert("<head><title># 1</title></head><p>Body</p>"),
+        "# \\# 1\n\nBody"
+    );
+}
+
+#[test]
+fn test_emit_frontmatter() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        emit_frontmatter: true,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    assert_option_fixture("frontmatter", &turndown);
+    assert_eq!(
+        turndown.convert("<html><head><title>Empty</title></head><body></body></html>"),
+        "---\ntitle: \"Empty\"\n---"
+    );
+    assert_eq!(turndown.convert("<p>No head</p>"), "No head");
+}
+
+#[test]
+fn test_append_image_dimensions() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        append_image_dimensions: true,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = r#"<img src="https://example.com/hero.png" alt="Hero" width="1200" height="800">"#;
+    assert_eq!(
+        turndown.convert(html),
+        "![Hero (1200x800)](https://example.com/hero.png)"
+    );
+
+    // Images without both dimensions are left untouched
+    let html = r#"<img src="https://example.com/hero.png" alt="Hero" width="1200">"#;
+    assert_eq!(
+        turndown.convert(html),
+        "![Hero](https://example.com/hero.png)"
+    );
+}
+
+#[test]
+fn test_max_alt_length() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        max_alt_length: Some(12),
+        append_image_dimensions: true,
+        ..Default::default()
+    });
+
+    let alt = "A photo of the harbour at dawn with fishing boats returning";
+    assert_eq!(
+        turndown.convert(&format!(
+            r#"<img src="a.jpg" alt="{}" title="{}">"#,
+            alt, alt
+        )),
+        r#"![A photo of t…](a.jpg "A photo of t…")"#
+    );
+    // Multibyte text is cut between characters, and dimensions are kept whole
+    assert_eq!(
+        turndown.convert(
+            r#"<img src="b.jpg" alt="Ein Schiff fährt über den Fluss" width="40" height="30">"#
+        ),
+        "![Ein Schiff f… (40x30)](b.jpg)"
+    );
+    assert_eq!(
+        turndown.convert(r#"<img src="c.jpg" alt="港に帰る漁船の写真です、夜明けの港">"#),
+        "![港に帰る漁船の写真です、…](c.jpg)"
+    );
+    assert_eq!(
+        turndown.convert(r#"<img src="d.jpg" alt="Short">"#),
+        "![Short](d.jpg)"
+    );
+}
+
+#[test]
+fn test_emoji_images_as_text_disabled() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        emoji_images_as_text: false,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = r#"<img class="emoji" alt="🎉" src="https://example.com/party.png">"#;
+    assert_eq!(
+        turndown.convert(html),
+        "![🎉](https://example.com/party.png)"
+    );
+}
+
+#[test]
+fn test_bidi_elements_keep_text() {
+    let turndown = Turndown::new();
+
+    let html = r#"<p>User <bdi>إيان</bdi>: 90 points</p>"#;
+    assert_eq!(turndown.convert(html), "User إيان: 90 points");
+
+    let html = r#"<p>Before <bdo dir="rtl">reversed</bdo> after, long<wbr>word</p>"#;
+    assert_eq!(turndown.convert(html), "Before reversed after, longword");
+}
+
+#[test]
+fn test_bidi_override_controls() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        bidi_override_controls: true,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = r#"<p>Before <bdo dir="rtl">reversed</bdo> after</p>"#;
+    assert_eq!(
+        turndown.convert(html),
+        "Before \u{202E}reversed\u{202C} after"
+    );
+}
+
+#[test]
+fn test_caption_placement() {
+    use turndown::{CaptionPlacement, TurndownOptions};
+
+    let options = TurndownOptions {
+        figure_caption_placement: CaptionPlacement::Above,
+        table_caption_placement: CaptionPlacement::Below,
+        ..Default::default()
+    };
+
+    assert_option_fixture("captions_swapped", &Turndown::with_options(options));
+}
+
+#[test]
+fn test_link_destination_with_spaces() {
+    let turndown = Turndown::new();
+
+    let html = r#"<a href="https://example.com/my file.pdf">Download</a>"#;
+    assert_eq!(
+        turndown.convert(html),
+        "[Download](<https://example.com/my file.pdf>)"
+    );
+
+    let html = r#"<a href="https://example.com/a b>c">Odd</a>"#;
+    assert_eq!(
+        turndown.convert(html),
+        "[Odd](<https://example.com/a b%3Ec>)"
+    );
+}
+
+#[test]
+fn test_keep_empty_links() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        drop_empty_links: false,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = r#"<p>Read more<a href="https://example.com/empty"></a></p>"#;
+    assert_eq!(
+        turndown.convert(html),
+        "Read more[](https://example.com/empty)"
+    );
+}
+
+#[test]
+fn test_preserve_link_attributes() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        preserve_link_attributes: vec!["download".to_string(), "target".to_string()],
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = r#"<p>Get the <a href="/report.pdf" download>annual <b>report</b></a> or <a href="/about">read more</a>.</p>"#;
+    assert_eq!(
+        turndown.convert(html),
+        r#"Get the <a download href="/report.pdf">annual **report**</a> or [read more](/about)."#
+    );
+}
+
+#[test]
+fn test_hidden_preheader_detection() {
+    let turndown = Turndown::new();
+
+    let html = r#"<div style="display:none; overflow:hidden; max-height:0">Preheader text</div><p>Body</p>"#;
+    assert_eq!(turndown.convert(html), "Body");
+
+    // Class and style values that merely contain the preheader signatures are content
+    let html = r#"<div class="h-0 opacity-0" style="visibility:hidden; max-height:0">Hidden</div>"#;
+    assert_eq!(turndown.convert(html), "");
+    let html =
+        r#"<div class="h-0x opacity-0" style="visibility:hidden; max-height:0">Visible?</div>"#;
+    assert_eq!(turndown.convert(html), "Visible?");
+
+    let html =
+        r#"<div class="h-0 opacity-0" style="display:none-ish; overflow:hidden">Content</div>"#;
+    assert_eq!(turndown.convert(html), "Content");
+}
+
+#[test]
+fn test_preheader_detection_off() {
+    use turndown::{PreheaderMode, TurndownOptions};
+
+    let options = TurndownOptions {
+        preheader_detection: PreheaderMode::Off,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = r#"<div data-email-preheader>Preview text</div><p>Body</p>"#;
+    assert_eq!(turndown.convert(html), "Preview text\n\nBody");
+}
+
+#[test]
+fn test_preheader_detection_custom() {
+    use turndown::{PreheaderMode, TurndownOptions};
+
+    let options = TurndownOptions {
+        preheader_detection: PreheaderMode::Custom(|node| node.has_class("preview")),
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = r#"<span class="preview">Preview text</span><div data-email-preheader>Kept</div>"#;
+    assert_eq!(turndown.convert(html), "Kept");
+}
+
+#[test]
+fn test_list_marker_spacing() {
+    use turndown::TurndownOptions;
+
+    let html =
+        "<ul><li>Apple<ul><li>Core</li></ul></li></ul><ol><li>First</li><li>Second</li></ol>";
+
+    let turndown = Turndown::new();
+    assert_eq!(
+        turndown.convert(html),
+        "* Apple\n  * Core\n\n1. First\n2. Second"
+    );
+
+    let options = TurndownOptions {
+        list_marker_spacing: 3,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+    assert_eq!(
+        turndown.convert(html),
+        "*   Apple\n    *   Core\n\n1.   First\n2.   Second"
+    );
+}
+
+#[test]
+fn test_quote_attribution_without_dash() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        quote_attribution_dash: false,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html =
+        "<blockquote><p>Stay hungry.</p><footer><cite>Steve Jobs</cite></footer></blockquote>";
+    assert_eq!(turndown.convert(html), "> Stay hungry.\n>\n> Steve Jobs");
+
+    let html = "<blockquote><p>Stay hungry.</p><cite>Steve Jobs</cite></blockquote>";
+    assert_eq!(turndown.convert(html), "> Stay hungry.\n>\n> Steve Jobs");
+}
+
+#[test]
+fn test_tracking_heuristics_long_query() {
+    use turndown::{TrackingHeuristics, TurndownOptions};
+
+    let options = TurndownOptions {
+        strip_tracking_images: true,
+        tracking_heuristics: TrackingHeuristics {
+            max_query_length: Some(80),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    let html = format!(
+        r#"<p>Hi</p><img src="https://mail.example.com/o/e.png?id={}" alt="x"><img src="https://example.com/logo.png?v=2" alt="Logo">"#,
+        "Zm9vYmFyYmF6".repeat(10)
+    );
+    assert_eq!(
+        turndown.convert(&html),
+        "Hi\n\n![Logo](https://example.com/logo.png?v=2)"
+    );
+}
+
+#[test]
+fn test_unwrap_tracking_redirects() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        unwrap_tracking_redirects: true,
+        ..Default::default()
+    };
+
+    assert_option_fixture("tracking_redirects", &Turndown::with_options(options));
+}
+
+#[test]
+fn test_custom_rule_overrides_builtin() {
+    use turndown::{Rule, RuleFilter};
+
+    let mut turndown = Turndown::new();
+    turndown.add_rule(
+        "underscoreStrong".to_string(),
+        Rule {
+            filter: RuleFilter::Array(vec!["strong".to_string(), "b".to_string()]),
+            replacement: |content, _, _| format!("__{}__", content),
+        },
+    );
+
+    assert_eq!(
+        turndown.convert("<p><strong>Bold</strong> and <b>this</b></p>"),
+        "__Bold__ and __this__"
+    );
+    assert_eq!(
+        turndown.convert("<p><em>Still</em> built in</p>"),
+        "_Still_ built in"
+    );
+}
+
+#[test]
+fn test_custom_default_rule() {
+    use turndown::{Rule, RuleFilter};
+
+    let mut turndown = Turndown::new();
+    turndown.set_default_rule(Rule {
+        filter: RuleFilter::String("default".to_string()),
+        replacement: |content, node, _| {
+            let tag = node.node_name.to_lowercase();
+            format!("<!-- {} -->{}<!-- /{} -->", tag, content, tag)
+        },
+    });
+
+    let markdown = turndown.convert("<p>Hello <blink>there</blink>, <em>friend</em></p>");
+    assert!(markdown.contains("Hello <!-- blink -->there<!-- /blink -->, _friend_"));
+}
+
+#[test]
+fn test_latest_custom_rule_takes_precedence() {
+    use turndown::{Rule, RuleFilter};
+
+    let mut turndown = Turndown::new();
+    turndown.add_rule(
+        "strikethrough".to_string(),
+        Rule {
+            filter: RuleFilter::Array(vec!["s".to_string(), "strike".to_string()]),
+            replacement: |content, _, _| format!("~{}~", content),
+        },
+    );
+    turndown.add_rule(
+        "strikeTag".to_string(),
+        Rule {
+            filter: RuleFilter::String("strike".to_string()),
+            replacement: |content, _, _| format!("~~{}~~", content),
+        },
+    );
+
+    for _ in 0..10 {
+        assert_eq!(
+            turndown.convert("<p><s>one</s> and <strike>two</strike></p>"),
+            "~one~ and ~~two~~"
+        );
+    }
+}
+
+#[test]
+fn test_add_rules_keeps_given_order() {
+    use turndown::{Rule, RuleFilter};
+
+    let mut turndown = Turndown::new();
+    turndown.add_rules(vec![
+        (
+            "strikeTag".to_string(),
+            Rule {
+                filter: RuleFilter::String("strike".to_string()),
+                replacement: |content, _, _| format!("~~{}~~", content),
+            },
+        ),
+        (
+            "strikethrough".to_string(),
+            Rule {
+                filter: RuleFilter::Array(vec!["s".to_string(), "strike".to_string()]),
+                replacement: |content, _, _| format!("~{}~", content),
+            },
+        ),
+    ]);
+
+    assert_eq!(
+        turndown.convert("<p><s>one</s> and <strike>two</strike></p>"),
+        "~one~ and ~~two~~"
+    );
+}
+
+#[test]
+fn test_typography_to_ascii() {
+    use turndown::{TurndownOptions, TypographyMode};
+
+    let html = "<p>&#8220;It&#8217;s fine,&#8221; she said&#8230; a non&#8209;issue.</p>";
+    assert_eq!(
+        Turndown::new().convert(html),
+        "\u{201C}It\u{2019}s fine,\u{201D} she said\u{2026} a non\u{2011}issue."
+    );
+
+    let options = TurndownOptions {
+        typography: TypographyMode::ToAscii,
+        ..Default::default()
+    };
+    assert_eq!(
+        Turndown::with_options(options).convert(html),
+        "\"It's fine,\" she said... a non-issue."
+    );
+}
+
+#[test]
+fn test_whitespace_preserve_line_breaks() {
+    use turndown::{TurndownOptions, WhitespaceMode};
+
+    let options = TurndownOptions {
+        whitespace_mode: WhitespaceMode::PreserveLineBreaks,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    assert_option_fixture("preserve_line_breaks", &turndown);
+    assert_eq!(turndown.convert("<p>one\ntwo</p>"), "one  \ntwo");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_options_serde_round_trip() {
+    use turndown::{HeadingStyle, TurndownOptions};
+
+    let config = r#"{
+        "heading_style": "Setext",
+        "em_delimiter": "*",
+        "strip_tracking_images": true,
+        "tracking_image_regex": "(?i)pixel\\.gif"
+    }"#;
+    let options: TurndownOptions = serde_json::from_str(config).unwrap();
+    assert_eq!(options.heading_style, HeadingStyle::Setext);
+    assert_eq!(options.em_delimiter, "*");
+    assert_eq!(options.bullet_list_marker, "*");
+
+    let json = serde_json::to_string(&options).unwrap();
+    let options: TurndownOptions = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        options
+            .tracking_image_regex
+            .as_ref()
+            .map(|regex| regex.as_str()),
+        Some("(?i)pixel\\.gif")
+    );
+
+    let turndown = Turndown::with_options(options);
+    let html = r#"<h1>Title</h1><p><img src="https://x.test/pixel.gif" alt="t"><em>Hi</em></p>"#;
+    assert_eq!(turndown.convert(html), "Title\n=====\n\n*Hi*");
+    assert!(serde_json::from_str::<TurndownOptions>(r#"{"tracking_image_regex": "("}"#).is_err());
+}
+
+#[test]
+fn test_list_item_line_break_alignment() {
+    let turndown = Turndown::new();
+
+    assert_eq!(
+        turndown.convert("<ul><li>line1<br>line2</li></ul>"),
+        "* line1  \n  line2"
+    );
+    assert_eq!(
+        turndown.convert("<ol><li>line1<br>line2</li></ol>"),
+        "1. line1  \n   line2"
+    );
+}
+
+#[test]
+fn test_blockquote_line_break() {
+    let turndown = Turndown::new();
+
+    assert_eq!(
+        turndown.convert("<blockquote>line1<br>line2</blockquote>"),
+        "> line1  \n> line2"
+    );
+    assert_eq!(
+        turndown.convert("<blockquote>line1<br><br>line2</blockquote>"),
+        "> line1  \n>\n> line2"
+    );
+}
+
+#[test]
+fn test_autolink_bare_urls() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        autolink_bare_urls: true,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    assert_eq!(
+        turndown.convert("<p>See https://example.com/a_b?x=1 for details.</p>"),
+        "See <https://example.com/a_b?x=1> for details."
+    );
+    assert_eq!(
+        turndown.convert("<p>Docs (https://example.com/docs) and more</p>"),
+        "Docs (<https://example.com/docs>) and more"
+    );
+    assert_eq!(
+        turndown.convert("<p>Run <code>curl https://example.com/a_b</code> now</p>"),
+        "Run `curl https://example.com/a_b` now"
+    );
+    assert_eq!(
+        turndown.convert(r#"<p><a href="https://example.com">https://example.com</a></p>"#),
+        "[https://example.com](https://example.com)"
+    );
+    assert_eq!(
+        Turndown::new().convert("<p>See https://example.com/a_b</p>"),
+        "See https://example.com/a\\_b"
+    );
+}
+
+#[test]
+fn test_conversion_is_deterministic() {
+    let html = r#"<div data-email-preheader>Hidden</div>
+        <table><tr><td class="list-item-prefix">•</td><td class="list-item">First</td></tr></table>
+        <h2>Title</h2><p>Some <b>bold</b>, <i>italic</i> and <a href="https://a.test">a link</a>.</p>"#;
+
+    let expected = Turndown::new().convert(html);
+    for _ in 0..50 {
+        assert_eq!(Turndown::new().convert(html), expected);
+    }
+}
+
+#[test]
+fn test_drop_empty_table_rows() {
+    use turndown::TurndownOptions;
+
+    assert_option_fixture(
+        "table_spacer_rows",
+        &Turndown::with_options(TurndownOptions::with_gfm()),
+    );
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        drop_empty_table_rows: false,
+        ..TurndownOptions::with_gfm()
+    });
+    assert_eq!(
+        turndown
+            .convert("<table><tr><th>A</th><th>B</th></tr><tr><td> </td><td> </td></tr></table>"),
+        "| A | B |\n| --- | --- |\n| |  |"
+    );
+}
+
+#[test]
+fn test_ragged_table_rows_are_padded() {
+    use turndown::{gfm_rules, TurndownOptions};
+
+    let mut options = TurndownOptions::default();
+    options.rules.extend(gfm_rules::get_rules());
+
+    assert_option_fixture("ragged_table", &Turndown::with_options(options));
+}
+
+#[test]
+fn test_gfm_options_convert_tables() {
+    use turndown::TurndownOptions;
+
+    let html = "<table><tr><th>Name</th><th>Qty</th></tr><tr><td>Apple</td><td>3</td></tr></table>";
+
+    let gfm = Turndown::with_options(TurndownOptions::with_gfm());
+    assert_eq!(
+        gfm.convert(html),
+        "| Name | Qty |\n| --- | --- |\n| Apple | 3 |"
+    );
+
+    let commonmark = Turndown::new().convert(html);
+    assert!(!commonmark.contains('|'));
+}
+
+#[test]
+fn test_table_cell_code_spans() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions::with_gfm());
+    assert_option_fixture("table_code_pipes", &turndown);
+}
+
+#[test]
+fn test_table_cell_line_breaks() {
+    use turndown::{gfm_rules, TurndownOptions};
+
+    let mut options = TurndownOptions::default();
+    options.rules.extend(gfm_rules::get_rules());
+    let turndown = Turndown::with_options(options);
+
+    assert_option_fixture("table_line_breaks", &turndown);
+    assert!(turndown
+        .convert("<p>Outside a table<br>the break stays</p>")
+        .contains("table  \nthe"));
+}
+
+#[test]
+fn test_deeply_nested_html() {
+    let depth = 5000;
+    let html = format!(
+        "{}<p>deep</p>{}",
+        "<div>".repeat(depth),
+        "</div>".repeat(depth)
+    );
+
+    assert_eq!(Turndown::new().convert(&html), "deep");
+}
+
+#[test]
+fn test_acronym_matches_abbr() {
+    let turndown = Turndown::new();
+
+    let abbr =
+        turndown.convert(r#"<p>Ask the <abbr title="World Health Organization">WHO</abbr>.</p>"#);
+    let acronym = turndown
+        .convert(r#"<p>Ask the <acronym title="World Health Organization">WHO</acronym>.</p>"#);
+    assert_eq!(abbr, "Ask the WHO.");
+    assert_eq!(acronym, abbr);
+}
+
+#[test]
+fn test_font_as_html() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        font_as_html: true,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+
+    assert_eq!(
+        turndown.convert(r#"<p><font color="red" size="5" face="Georgia"><b>Sale</b></font></p>"#),
+        r#"<span style="color: red; font-size: x-large; font-family: Georgia">**Sale**</span>"#
+    );
+    assert_eq!(turndown.convert("<p><font>plain</font></p>"), "plain");
+}
+
+#[test]
+fn test_blockquote_cite() {
+    use turndown::TurndownOptions;
+
+    let html = r#"<blockquote cite="https://example.com/speech"><p>Stay hungry.</p></blockquote>"#;
+    assert_eq!(Turndown::new().convert(html), "> Stay hungry.");
+
+    let options = TurndownOptions {
+        blockquote_cite: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        Turndown::with_options(options).convert(html),
+        "> Stay hungry.\n>\n> — <https://example.com/speech>"
+    );
+}
+
+#[test]
+fn test_kbd_style_html() {
+    use turndown::{KbdStyle, TurndownOptions};
+
+    let options = TurndownOptions {
+        kbd_style: KbdStyle::Html,
+        ..Default::default()
+    };
+    assert_eq!(
+        Turndown::with_options(options).convert("<p>Press <kbd>Ctrl</kbd>+<kbd>C</kbd></p>"),
+        "Press <kbd>Ctrl</kbd>+<kbd>C</kbd>"
+    );
+}
+
+#[test]
+fn test_samp_rendering() {
+    use turndown::TurndownOptions;
+
+    let html = "<p>The shell printed <samp>file_not_found</samp>.</p>";
+    assert_eq!(
+        Turndown::new().convert(html),
+        "The shell printed `file_not_found`."
+    );
+
+    let options = TurndownOptions {
+        samp_as_code: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        Turndown::with_options(options).convert(html),
+        "The shell printed file\\_not\\_found."
+    );
+}
+
+#[test]
+fn test_output_rendering() {
+    use turndown::TurndownOptions;
+
+    let html = "<p>Sum: <output>42</output></p>";
+    assert_eq!(Turndown::new().convert(html), "Sum: 42");
+
+    let options = TurndownOptions {
+        output_as_code: true,
+        ..Default::default()
+    };
+    let turndown = Turndown::with_options(options);
+    assert_eq!(turndown.convert(html), "Sum: `42`");
+
+    // Output is phrasing content, so the space before it is kept
+    assert_eq!(
+        Turndown::new().convert("<p><span>a</span> <output>b</output></p>"),
+        "a b"
+    );
+    assert_eq!(
+        turndown.convert("<p><samp>out</samp> <output>42</output></p>"),
+        "`out` `42`"
+    );
+}
+
+#[test]
+fn test_intraword_emphasis() {
+    let turndown = Turndown::new();
+
+    assert_eq!(
+        turndown.convert("<p>un<em>be</em>lievable</p>"),
+        "un*be*lievable"
+    );
+    assert_eq!(
+        turndown.convert("<p><i>un</i>believable</p>"),
+        "*un*believable"
+    );
+    assert_eq!(
+        turndown.convert("<p>a <em>word</em> here</p>"),
+        "a _word_ here"
+    );
+
+    let options = turndown::TurndownOptions {
+        strong_delimiter: "__".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        Turndown::with_options(options).convert("<p>foo<b>bar</b>baz</p>"),
+        "foo**bar**baz"
+    );
+}
+
+#[test]
+fn test_layout_tables_are_flattened() {
+    use turndown::{gfm_rules, TurndownOptions};
+
+    let mut options = TurndownOptions {
+        detect_layout_tables: true,
+        ..Default::default()
+    };
+    options.rules.extend(gfm_rules::get_rules());
+    let turndown = Turndown::with_options(options);
+
+    assert_option_fixture("layout_table", &turndown);
+    assert_option_fixture("data_table", &turndown);
+
+    // A heading row of plain cells in a THEAD still marks a data table
+    assert_eq!(
+        turndown.convert("<table><thead><tr><td>A</td><td>B</td></tr></thead><tr><td>1</td><td>2</td></tr></table>"),
+        "| A | B |\n| --- | --- |\n| 1 | 2 |"
+    );
+
+    // Without the heuristics, only tables with a presentation role are flattened
+    let mut options = TurndownOptions::default();
+    options.rules.extend(gfm_rules::get_rules());
+    assert_eq!(
+        Turndown::with_options(options).convert("<table><tr><td>1</td><td>2</td></tr></table>"),
+        "|  |  |\n| --- | --- |\n| 1 | 2 |"
+    );
+}
+
+#[test]
+fn test_presentation_tables_are_flattened() {
+    use turndown::{gfm_rules, TurndownOptions};
+
+    // The role is explicit, so it applies even without the layout heuristics
+    let mut options = TurndownOptions {
+        detect_layout_tables: false,
+        ..Default::default()
+    };
+    options.rules.extend(gfm_rules::get_rules());
+    let turndown = Turndown::with_options(options);
+
+    assert_option_fixture("presentation_table", &turndown);
+    assert!(!turndown
+        .convert(&fs::read_to_string("tests/option_fixtures/presentation_table.html").unwrap())
+        .contains('|'));
+}
+
+#[test]
+fn test_svg_as_html() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        svg_as_html: true,
+        ..Default::default()
+    });
+
+    assert_option_fixture("inline_svg_html", &turndown);
+}
+
+#[test]
+fn test_nested_emphasis_alternates_delimiters() {
+    let turndown = Turndown::new();
+
+    assert_eq!(
+        turndown.convert("<p><em><em>both</em></em></p>"),
+        "_*both*_"
+    );
+    assert_eq!(
+        turndown.convert("<p><i>a <i>b <i>c</i></i></i></p>"),
+        "_a *b _c_*_"
+    );
+    assert_eq!(
+        turndown.convert("<p><b><strong>loud</strong></b></p>"),
+        "**__loud__**"
+    );
+    assert_eq!(
+        turndown.convert("<p><em>note: <strong>important</strong></em></p>"),
+        "_note: **important**_"
+    );
+    assert_eq!(
+        turndown.convert("<p><strong><em>both</em></strong></p>"),
+        "**_both_**"
+    );
+
+    let options = turndown::TurndownOptions {
+        em_delimiter: "*".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(
+        Turndown::with_options(options).convert("<p><em><em>both</em></em></p>"),
+        "*_both_*"
+    );
+}
+
+#[test]
+fn test_dash_bullets_escape_line_start_markers() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        bullet_list_marker: "-".to_string(),
+        ..Default::default()
+    });
+
+    assert_option_fixture("dash_bullets", &turndown);
+}
+
+#[test]
+fn test_section_separator() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        section_separator: Some("* * *".to_string()),
+        ..Default::default()
+    });
+    assert_option_fixture("section_separator", &turndown);
+
+    let html = "<section><p>One</p></section><section><p>Two</p></section>";
+    assert_eq!(Turndown::new().convert(html), "One\n\nTwo");
+}
+
+#[test]
+fn test_preserve_empty_block_spacing() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        preserve_empty_block_spacing: true,
+        ..Default::default()
+    });
+    // Compared exactly, as the fixture harness collapses the kept blank lines
+    let html = fs::read_to_string("tests/option_fixtures/empty_block_spacing.html").unwrap();
+    let expected = fs::read_to_string("tests/option_fixtures/empty_block_spacing.md").unwrap();
+    let markdown = turndown.convert(&html);
+    assert_eq!(markdown, expected.trim_end_matches('\n'));
+    assert!(markdown.contains("order!\n\n\nYour package"));
+    assert!(!Turndown::new().convert(&html).contains("\n\n\n"));
+
+    let html = "<p>One</p><p>&nbsp;</p><p>Two</p>";
+    assert_eq!(turndown.convert(html), "One\n\n\nTwo");
+    assert_eq!(Turndown::new().convert(html), "One\n\nTwo");
+}
+
+#[test]
+fn test_code_language_hints() {
+    use turndown::TurndownOptions;
+
+    let python = "<pre><code>#!/usr/bin/env python3\nprint(\"hi\")</code></pre>";
+    let bash = "<pre>#!/bin/bash\necho hi</pre>";
+    let comment = "<pre><code><!-- lang: sql -->\nSELECT 1;</code></pre>";
+    let class = "<pre><code class=\"language-ruby\">#!/usr/bin/env python\n</code></pre>";
+
+    assert_eq!(
+        Turndown::new().convert(python),
+        "```\n#!/usr/bin/env python3\nprint(\"hi\")\n```"
+    );
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        code_language_hints: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        turndown.convert(python),
+        "```python\n#!/usr/bin/env python3\nprint(\"hi\")\n```"
+    );
+    assert_eq!(turndown.convert(bash), "```bash\n#!/bin/bash\necho hi\n```");
+    assert_eq!(turndown.convert(comment), "```sql\nSELECT 1;\n```");
+    // A class takes precedence over the hints
+    assert!(turndown.convert(class).starts_with("```ruby\n"));
+}
+
+#[test]
+fn test_underline_modes() {
+    use turndown::{TurndownOptions, UnderlineMode};
+
+    let html = "<p>Please <u>read carefully</u> before signing.</p>";
+    let convert = |underline_mode| {
+        Turndown::with_options(TurndownOptions {
+            underline_mode,
+            ..Default::default()
+        })
+        .convert(html)
+    };
+
+    assert_eq!(
+        Turndown::new().convert(html),
+        "Please <u>read carefully</u> before signing."
+    );
+    assert_eq!(
+        convert(UnderlineMode::KeepHtml),
+        "Please <u>read carefully</u> before signing."
+    );
+    assert_eq!(
+        convert(UnderlineMode::Emphasis),
+        "Please _read carefully_ before signing."
+    );
+    assert_eq!(
+        convert(UnderlineMode::Drop),
+        "Please read carefully before signing."
+    );
+}
+
+#[test]
+fn test_mark_color_modes() {
+    use turndown::{MarkColorMode, TurndownOptions};
+
+    let html =
+        r#"<p>Note the <mark data-color="yellow">due date</mark> and <mark>total</mark>.</p>"#;
+    let convert = |mark_color| {
+        Turndown::with_options(TurndownOptions {
+            mark_color,
+            ..Default::default()
+        })
+        .convert(html)
+    };
+
+    assert_eq!(
+        Turndown::new().convert(html),
+        "Note the ==due date== and ==total==."
+    );
+    assert_eq!(
+        convert(MarkColorMode::Comment),
+        "Note the ==due date==<!-- yellow --> and ==total==."
+    );
+    assert_eq!(
+        convert(MarkColorMode::KeepHtml),
+        r#"Note the <mark data-color="yellow">due date</mark> and ==total==."#
+    );
+
+    let styled = r#"<mark style="background-color: #ff0">Late</mark>"#;
+    let turndown = Turndown::with_options(TurndownOptions {
+        mark_color: MarkColorMode::Comment,
+        ..Default::default()
+    });
+    assert_eq!(turndown.convert(styled), "==Late==<!-- #ff0 -->");
+}
+
+#[test]
+fn test_encoded_tags_stay_text() {
+    let turndown = Turndown::new();
+
+    let html = "<p>Use a &lt;div&gt; instead of &lt;table&gt; when 3 &lt; 4.</p>";
+    let markdown = turndown.convert(html);
+    assert_eq!(markdown, "Use a \\<div> instead of \\<table> when 3 < 4.");
+
+    // Double-encoded entities come out as the entity text, not the character
+    assert_eq!(
+        turndown.convert("<p>Write &amp;amp; for &amp;</p>"),
+        "Write \\&amp; for &"
+    );
+}
+
+#[test]
+fn test_reference_link_labels() {
+    use turndown::{LinkStyle, ReferenceLabels, TurndownOptions};
+
+    let html = r#"<p>Read the <a href="https://example.com/notes">Release notes</a>, the <a href="https://example.com/docs">Docs</a> and the <a href="https://example.com/notes">notes</a> again.</p>"#;
+    let convert = |labels| {
+        let mut options = TurndownOptions {
+            link_style: LinkStyle::Referenced,
+            ..Default::default()
+        };
+        options.link_reference_format.labels = labels;
+        Turndown::with_options(options).convert(html)
+    };
+
+    assert_eq!(
+        convert(ReferenceLabels::Numeric),
+        "Read the [Release notes][1], the [Docs][2] and the [notes][1] again.\n\n\
+         [1]: https://example.com/notes\n\
+         [2]: https://example.com/docs"
+    );
+    assert_eq!(
+        convert(ReferenceLabels::Slug),
+        "Read the [Release notes][release-notes], the [Docs][docs] and the [notes][release-notes] again.\n\n\
+         [release-notes]: https://example.com/notes\n\
+         [docs]: https://example.com/docs"
+    );
+}
+
+#[test]
+fn test_text_labels_of_different_targets() {
+    use turndown::{LinkReferenceStyle, LinkStyle, TurndownOptions};
+
+    let html =
+        r#"<p><a href="https://a.test/">Docs</a> and <a href="https://b.test/">docs</a></p>"#;
+    let convert = |style| {
+        Turndown::with_options(TurndownOptions {
+            link_style: LinkStyle::Referenced,
+            link_reference_style: style,
+            ..Default::default()
+        })
+        .convert(html)
+    };
+
+    assert_eq!(
+        convert(LinkReferenceStyle::Shortcut),
+        "[Docs] and [docs][docs-2]\n\n[Docs]: https://a.test/\n[docs-2]: https://b.test/"
+    );
+    assert_eq!(
+        convert(LinkReferenceStyle::Collapsed),
+        "[Docs][] and [docs][docs-2]\n\n[Docs]: https://a.test/\n[docs-2]: https://b.test/"
+    );
+}
+
+#[test]
+fn test_reference_link_order() {
+    use turndown::{LinkStyle, ReferenceOrder, TurndownOptions};
+
+    let html = r#"<p><a href="https://b.test/1">One</a> <a href="https://a.test/2">Two</a> <a href="https://b.test/3">Three</a></p>"#;
+    let convert = |order| {
+        let mut options = TurndownOptions {
+            link_style: LinkStyle::Referenced,
+            ..Default::default()
+        };
+        options.link_reference_format.order = order;
+        let markdown = Turndown::with_options(options).convert(html);
+        markdown.split_once("\n\n").unwrap().1.to_string()
+    };
+
+    assert_eq!(
+        convert(ReferenceOrder::FirstAppearance),
+        "[1]: https://b.test/1\n[2]: https://a.test/2\n[3]: https://b.test/3"
+    );
+    assert_eq!(
+        convert(ReferenceOrder::ByUrl),
+        "[2]: https://a.test/2\n[1]: https://b.test/1\n[3]: https://b.test/3"
+    );
+    assert_eq!(
+        convert(ReferenceOrder::GroupedByHost),
+        "[1]: https://b.test/1\n[3]: https://b.test/3\n\n[2]: https://a.test/2"
+    );
+}
+
+#[test]
+fn test_dedupe_manual_list_markers() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        dedupe_manual_list_markers: true,
+        ..Default::default()
+    });
+    assert_option_fixture("manual_list_markers", &turndown);
+
+    // Typed numbers are compared with the numbers the items are rendered with
+    assert_eq!(
+        turndown.convert(r#"<ol start="3"><li>3. a</li><li>4. b</li></ol>"#),
+        "3. a\n4. b"
+    );
+    assert_eq!(
+        turndown.convert(r#"<ol start="3"><li>1. a</li><li>2. b</li></ol>"#),
+        "3. 1\\. a\n4. 2\\. b"
+    );
+    assert_eq!(
+        turndown.convert(r#"<ol><li>1. a</li><li value="5">5. b</li><li>6. c</li></ol>"#),
+        "1. a\n5. b\n6. c"
+    );
+
+    let html = "<ol><li>1. First</li><li>2. Second</li></ol>";
+    assert_eq!(
+        Turndown::new().convert(html),
+        "1. 1\\. First\n2. 2\\. Second"
+    );
+}
+
+#[test]
+fn test_setext_underline_matches_display_width() {
+    use turndown::{HeadingStyle, TurndownOptions};
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        heading_style: HeadingStyle::Setext,
+        ..Default::default()
+    });
+
+    assert_eq!(turndown.convert("<h1>你好世界</h1>"), "你好世界\n========");
+    assert_eq!(
+        turndown.convert("<h2>日本語 Guide</h2>"),
+        "日本語 Guide\n------------"
+    );
+    assert_eq!(turndown.convert("<h1>Café</h1>"), "Café\n====");
+}
+
+#[test]
+fn test_list_style_markers() {
+    use turndown::TurndownOptions;
+
+    let html = r#"<ul style="list-style-type: circle"><li>Circle</li></ul><ul type="square"><li>Square</li></ul><ul><li>Plain</li></ul>"#;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        list_style_markers: true,
+        ..Default::default()
+    });
+    assert_eq!(turndown.convert(html), "- Circle\n\n+ Square\n\n* Plain");
+
+    assert_eq!(
+        Turndown::new().convert(html),
+        "* Circle\n\n* Square\n\n* Plain"
+    );
+}
+
+#[test]
+fn test_reference_link_titles() {
+    use turndown::{LinkStyle, TurndownOptions};
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        link_style: LinkStyle::Referenced,
+        ..Default::default()
+    });
+    let html = r#"<p><a href="https://example.com/" title="Home page">Home</a>, <a href="https://example.com/" title="Our &quot;start&quot; page">start</a> and <a href="https://example.com/" title="Home page">back</a> or <a href="https://example.com/">plain</a></p>"#;
+
+    assert_eq!(
+        turndown.convert(html),
+        "[Home][1], [start][2] and [back][1] or [plain][3]\n\n\
+         [1]: https://example.com/ \"Home page\"\n\
+         [2]: https://example.com/ \"Our \\\"start\\\" page\"\n\
+         [3]: https://example.com/"
+    );
+}
+
+#[test]
+fn test_cdata_sections() {
+    use turndown::TurndownOptions;
+
+    // CDATA is text in SVG and MathML and a dropped bogus comment in HTML
+    let html = "<p>x <math><mi><![CDATA[y]]></mi></math><![CDATA[ hidden ]]> z</p>";
+    assert_eq!(Turndown::new().convert(html), "x y z");
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        svg_as_html: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        turndown.convert("<p><svg><text><![CDATA[a <b>&</b>]]></text></svg></p>"),
+        "<svg><text>a &lt;b&gt;&amp;&lt;/b&gt;</text></svg>"
+    );
+}
+
+#[test]
+fn test_semantic_templates() {
+    use std::collections::HashMap;
+    use turndown::TurndownOptions;
+
+    let mut templates = HashMap::new();
+    templates.insert("data".to_string(), "{text} [SKU {value}]".to_string());
+    templates.insert(
+        "TIME".to_string(),
+        "{text} ({datetime}) {missing}{".to_string(),
+    );
+    let turndown = Turndown::with_options(TurndownOptions {
+        semantic_templates: templates,
+        ..Default::default()
+    });
+
+    assert_eq!(
+        turndown.convert(r#"<p>Order <data value="A-1042"><em>Blue mug</em></data> today</p>"#),
+        "Order _Blue mug_ [SKU A-1042] today"
+    );
+    assert_eq!(
+        turndown.convert(r#"<p>Due <time datetime="2024-05-01">May 1</time>.</p>"#),
+        "Due May 1 (2024-05-01) {."
+    );
+    assert_eq!(
+        Turndown::new().convert(r#"<p><data value="A-1042">Blue mug</data></p>"#),
+        "Blue mug"
+    );
+}
+
+#[test]
+fn test_reassemble_split_text() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        autolink_bare_urls: true,
+        reassemble_split_text: true,
+        ..Default::default()
+    });
+    assert_option_fixture("split_urls", &turndown);
+
+    assert_eq!(
+        Turndown::new().convert("<p>Go to https://exa<wbr>mple.com now</p>"),
+        "Go to https://example.com now"
+    );
+}
+
+#[test]
+fn test_boilerplate_patterns() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        boilerplate_patterns: vec![
+            "unsubscribe".to_string(),
+            "View in browser".to_string(),
+            "footer".to_string(),
+        ],
+        ..Default::default()
+    });
+    assert_option_fixture("email_footer", &turndown);
+
+    // Matching text in a nested block only removes that block
+    assert_eq!(
+        turndown.convert("<div><p>News</p><p><a href=\"/u\">Unsubscribe</a></p></div>"),
+        "News"
+    );
+    assert_eq!(
+        Turndown::new().convert("<p><a href=\"/u\">Unsubscribe</a></p>"),
+        "[Unsubscribe](/u)"
+    );
+}
+
+#[test]
+fn test_indent_style() {
+    use turndown::{CodeBlockStyle, IndentStyle, TurndownOptions};
+
+    let html = "<ul><li>Fruit<ul><li>Apple<ul><li>Gala</li></ul></li></ul></li></ul>\
+                <pre><code>fn main() {\n    run();\n}</code></pre>";
+    let spaces = Turndown::with_options(TurndownOptions {
+        code_block_style: CodeBlockStyle::Indented,
+        ..Default::default()
+    });
+    assert_eq!(
+        spaces.convert(html),
+        "* Fruit\n  * Apple\n    * Gala\n\n    fn main() {\n        run();\n    }"
+    );
+
+    let tabs = Turndown::with_options(TurndownOptions {
+        code_block_style: CodeBlockStyle::Indented,
+        indent_style: IndentStyle::Tab,
+        ..Default::default()
+    });
+    assert_eq!(
+        tabs.convert(html),
+        "* Fruit\n\t* Apple\n\t\t* Gala\n\n\tfn main() {\n\t    run();\n\t}"
+    );
+    assert_eq!(tabs.convert("<pre><code>a\n\nb</code></pre>"), "\ta\n\n\tb");
+
+    let wide = Turndown::with_options(TurndownOptions {
+        code_block_style: CodeBlockStyle::Indented,
+        indent_style: IndentStyle::Spaces(4),
+        ..Default::default()
+    });
+    assert_eq!(
+        wide.convert(html),
+        "* Fruit\n    * Apple\n        * Gala\n\n    fn main() {\n        run();\n    }"
+    );
+
+    // Explicit spaces apply as given, unlike the marker-width default
+    let html = "<ol><li>a<ul><li>b</li></ul></li></ol><pre><code>c</code></pre>";
+    assert_eq!(spaces.convert(html), "1. a\n   * b\n\n    c");
+    let narrow = Turndown::with_options(TurndownOptions {
+        code_block_style: CodeBlockStyle::Indented,
+        indent_style: IndentStyle::Spaces(2),
+        ..Default::default()
+    });
+    assert_eq!(narrow.convert(html), "1. a\n  * b\n\n  c");
+}
+
+#[test]
+fn test_indented_code_opening_a_block() {
+    use turndown::{CodeBlockStyle, TurndownOptions};
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        code_block_style: CodeBlockStyle::Indented,
+        ..Default::default()
+    });
+    assert_eq!(
+        turndown.convert("<blockquote><pre><code>a\nb</code></pre></blockquote>"),
+        ">     a\n>     b"
+    );
+    assert_eq!(
+        turndown.convert("<ul><li><pre><code>a</code></pre></li></ul>"),
+        "*     a"
+    );
+}
+
+#[test]
+fn test_merge_adjacent_links() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        merge_adjacent_links: true,
+        ..Default::default()
+    });
+    assert_option_fixture("adjacent_links", &turndown);
+
+    assert_eq!(
+        Turndown::new().convert(r#"<a href="/x">Go</a><a href="/x">Go</a>"#),
+        "[Go](/x)[Go](/x)"
+    );
+}
+
+#[test]
+fn test_block_links_as_html() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        block_links_as_html: true,
+        ..Default::default()
+    });
+    assert_option_fixture("block_links", &turndown);
+
+    assert_eq!(
+        Turndown::new().convert(r#"<a href="/card"><div><h3>Title</h3></div></a>"#),
+        "[### Title](/card)"
+    );
+}
+
+#[test]
+fn test_pandoc_fenced_divs() {
+    use turndown::TurndownOptions;
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        pandoc_fenced_divs: true,
+        ..Default::default()
+    });
+    assert_option_fixture("fenced_divs", &turndown);
+
+    assert_eq!(
+        Turndown::new().convert(r#"<div class="warning"><p>Careful</p></div>"#),
+        "Careful"
+    );
+}
+
+#[test]
+fn test_center_mode() {
+    use turndown::{CenterMode, TurndownOptions};
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        center_mode: CenterMode::KeepHtml,
+        ..Default::default()
+    });
+    assert_option_fixture("centered_blocks", &turndown);
+
+    assert_eq!(
+        Turndown::new().convert("<center><h1>Spring Sale</h1></center>"),
+        "# Spring Sale"
+    );
+}
+
+#[test]
+fn test_inline_quotes() {
+    use turndown::TurndownOptions;
+
+    let html =
+        r#"<p>She wrote <q cite="https://example.com/letter">outer <q>inner</q> outer</q>.</p>"#;
+    assert_eq!(
+        Turndown::new().convert(html),
+        "She wrote \"outer 'inner' outer\"."
+    );
+
+    let turndown = Turndown::with_options(TurndownOptions {
+        quote_cite: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        turndown.convert(html),
+        "She wrote \"outer 'inner' outer\" (<https://example.com/letter>)."
+    );
+}
+
+#[test]
+fn test_code_next_to_punctuation_and_letters() {
+    let turndown = Turndown::new();
+
+    assert_eq!(turndown.convert("<p>Call <code>x</code>.</p>"), "Call `x`.");
+    assert_eq!(
+        turndown.convert("<p>(<code>x</code>), <code>y</code>!</p>"),
+        "(`x`), `y`!"
+    );
+    assert_eq!(turndown.convert("<p>a<code>x</code>b</p>"), "a`x`b");
+    assert_eq!(
+        turndown.convert("<p>Use <code> x </code> here</p>"),
+        "Use `x` here"
+    );
+    assert_eq!(
+        turndown.convert("<p>Use <em> x </em> here</p>"),
+        "Use _x_ here"
+    );
+    assert_eq!(turndown.convert("<p><code>a  b</code></p>"), "`a  b`");
+}
+
+#[test]
+fn test_ordered_list_numbers_do_not_overflow() {
+    let turndown = Turndown::new();
+
+    assert_eq!(
+        turndown.convert(r#"<ol start="18446744073709551615"><li>a</li><li>b</li></ol>"#),
+        "18446744073709551615. a\n18446744073709551615. b"
+    );
+    assert_eq!(
+        turndown.convert(r#"<ol><li value="18446744073709551615">a</li><li>b</li></ol>"#),
+        "18446744073709551615. a\n18446744073709551615. b"
+    );
+}
+
+#[test]
+fn test_heading_offset() {
+    use turndown::TurndownOptions;
+
+    let demoted = Turndown::with_options(TurndownOptions {
+        heading_offset: 1,
+        ..Default::default()
+    });
+    assert_eq!(demoted.convert("<h1>Title</h1>"), "## Title");
+    assert_eq!(demoted.convert("<h6>Fine print</h6>"), "###### Fine print");
+
+    let promoted = Turndown::with_options(TurndownOptions {
+        heading_offset: -1,
+        ..Default::default()
+    });
+    assert_eq!(promoted.convert("<h1>Title</h1>"), "# Title");
+    assert_eq!(promoted.convert("<h3>Section</h3>"), "## Section");
+}
+
+#[test]
+fn test_emphasis_safety() {
+    use turndown::TurndownOptions;
+
+    let safe = |em: &str, strong: &str| {
+        Turndown::with_options(TurndownOptions {
+            emphasis_safety: true,
+            em_delimiter: em.to_string(),
+            strong_delimiter: strong.to_string(),
+            ..Default::default()
+        })
+    };
+
+    // Touching spans of the same kind are merged instead of running together
+    assert_eq!(Turndown::new().convert("<em>a</em><em>b</em>"), "_a__b_");
+    assert_eq!(safe("_", "**").convert("<em>a</em><em>b</em>"), "_ab_");
+    assert_eq!(
+        safe("_", "**").convert("<strong>a</strong><b>b</b>"),
+        "**ab**"
+    );
+    assert_eq!(safe("_", "**").convert("<em>a</em> <em>b</em>"), "_a_ _b_");
+
+    // Content holding the delimiter character gets the other one
+    assert_eq!(
+        safe("*", "**").convert("<em>a <strong>b</strong> c</em>"),
+        "_a **b** c_"
+    );
+    assert_eq!(
+        safe("_", "__").convert("<strong><em>x</em> y</strong>"),
+        "**_x_ y**"
+    );
+    // Escaped characters and code spans cannot close the span
+    assert_eq!(safe("_", "**").convert("<em>a_b</em>"), "_a\\_b_");
+    assert_eq!(
+        safe("*", "**").convert("<em>x <code>*y*</code></em>"),
+        "*x `*y*`*"
+    );
+}
+
+#[test]
+fn test_raw_markdown_attribute() {
+    use turndown::TurndownOptions;
+
+    let options = TurndownOptions {
+        raw_markdown_attribute: Some("data-md-raw".to_string()),
+        ..Default::default()
+    };
+    assert_option_fixture("raw_markdown", &Turndown::with_options(options));
+
+    // Without the option the attribute means nothing
+    assert_eq!(
+        Turndown::new().convert(r#"<p><span data-md-raw="**x**">*y*</span></p>"#),
+        "\\*y\\*"
+    );
+}
+
+#[test]
+fn test_extract_tables() {
+    use turndown::{LinkStyle, TurndownOptions};
+
+    let turndown = Turndown::new();
+    let html = r#"
+        <p>Quarterly results</p>
+        <table>
+          <thead><tr><th>Region</th><th>Revenue</th></tr></thead>
+          <tbody><tr><td><a href="/emea">EMEA</a></td><td><b>1.2</b> M</td></tr></tbody>
+        </table>
+        <table><tr><td colspan="2">Total</td><td>3</td></tr></table>
+    "#;
+
+    assert_eq!(
+        turndown.extract_tables(html),
+        vec![
+            vec![
+                vec!["Region".to_string(), "Revenue".to_string()],
+                vec!["[EMEA](/emea)".to_string(), "**1.2** M".to_string()],
+            ],
+            vec![vec!["Total".to_string(), String::new(), "3".to_string()]],
+        ]
+    );
+    assert!(turndown.extract_tables("<p>No tables</p>").is_empty());
+
+    // Cells hold only their own text, without definitions or post-processing
+    let mut referenced = Turndown::with_options(TurndownOptions {
+        link_style: LinkStyle::Referenced,
+        ..Default::default()
+    });
+    referenced.set_post_processor(Box::new(|markdown| format!("{}\n\n-- footer", markdown)));
+    assert_eq!(
+        referenced.extract_tables(
+            r#"<table><tr><td><a href="/a" title="T">A</a> and <b>B</b></td></tr></table>"#
+        ),
+        vec![vec![vec![r#"[A](/a "T") and **B**"#.to_string()]]]
+    );
+}
+
+#[test]
+fn test_complex_table_mode() {
+    use turndown::{ComplexTableMode, TurndownOptions};
+
+    let flattened = Turndown::with_options(TurndownOptions::with_gfm());
+    assert_option_fixture("complex_table_flattened", &flattened);
+
+    let html = Turndown::with_options(TurndownOptions {
+        complex_table_mode: ComplexTableMode::KeepHtml,
+        ..TurndownOptions::with_gfm()
+    });
+    assert_option_fixture("complex_table_html", &html);
+
+    // Tables without block content in their cells are still pipe tables
+    assert_eq!(
+        html.convert("<table><tr><th>A</th><th>B</th></tr><tr><td>1</td><td>2</td></tr></table>"),
+        "| A | B |\n| --- | --- |\n| 1 | 2 |"
+    );
+}
+
+#[test]
+fn test_hashes_at_line_start() {
+    let turndown = Turndown::new();
+
+    assert_eq!(
+        turndown.convert("<p>#hashtag trends</p>"),
+        "#hashtag trends"
+    );
+    assert_eq!(turndown.convert("<p>####### x</p>"), "####### x");
+    assert_eq!(turndown.convert("<p># real</p>"), "\\# real");
+    assert_eq!(turndown.convert("<p>Total<br>#</p>"), "Total  \n\\#");
+}
+
+#[test]
+fn test_nested_links_are_escaped_once() {
+    use turndown::Node;
+
+    fn link(href: &str, children: Vec<Node>) -> Node {
+        let mut link = Node::new_element("a");
+        link.set_attribute("href", href);
+        link.children = children;
+        link
+    }
+
+    // HTML parsers close an open link at the next one, so the nesting is built directly
+    fn nest_links(node: &mut Node) {
+        if node.node_name == "P" {
+            let inner = link("/inner", vec![Node::new_text("[x] *y*")]);
+            node.children = vec![link("/outer", vec![Node::new_text("see "), inner])];
+        }
+        for child in &mut node.children {
+            nest_links(child);
+        }
+    }
+
+    let mut turndown = Turndown::new();
+    turndown.set_preprocessor(Box::new(nest_links));
+    assert_eq!(turndown.convert("<p>x</p>"), r"see [\[x\] \*y\*](/inner)");
+
+    // Text that only looks like a link is escaped and linked as usual
+    assert_eq!(
+        Turndown::new().convert(r#"<p><a href="/o">[not](a link)</a></p>"#),
+        r"[\[not\](a link)](/o)"
+    );
+}
+
+#[test]
+fn test_nav_link_lists() {
+    // Compared exactly, as the fixture harness would hide stray spaces and blank lines
+    let html = fs::read_to_string("tests/fixtures/nav_links.html").unwrap();
+    let expected = fs::read_to_string("tests/fixtures/nav_links.md").unwrap();
+
+    assert_eq!(
+        Turndown::new().convert(&html),
+        expected.trim_end_matches('\n')
+    );
+}
+
+#[test]
+fn test_spacing_around_links_in_sentences() {
+    let turndown = Turndown::new();
+
+    assert_eq!(
+        turndown.convert(r#"<p>a <a href="/x">link</a> b</p>"#),
+        "a [link](/x) b"
+    );
+    assert_eq!(
+        turndown.convert(r#"<p>a<a href="/x"> link </a>b</p>"#),
+        "a [link](/x) b"
+    );
+    assert_eq!(
+        turndown.convert(
+            r#"<p>See<a href="/x"> <b>bold</b> link</a>, or <a href="/y"><b>this </b></a>one</p>"#
+        ),
+        "See [**bold** link](/x), or [**this**](/y) one"
+    );
+    assert_eq!(
+        turndown.convert(r#"<p><a href="/a">A</a> <a href="/b">B</a> <a href="/c"> </a>end</p>"#),
+        "[A](/a) [B](/b) end"
+    );
+}
+
+#[test]
+fn test_spacing_across_inline_boundaries() {
+    let turndown = Turndown::new();
+
+    assert_eq!(turndown.convert("a <em>b</em> c"), "a _b_ c");
+    assert_eq!(turndown.convert("a<em> b </em>c"), "a _b_ c");
+    // Spaces on both sides of a boundary collapse into one
+    assert_eq!(turndown.convert("<p>a <em> b </em> c</p>"), "a _b_ c");
+    assert_eq!(
+        turndown.convert("<p>a <em><strong> b </strong></em> c</p>"),
+        "a _**b**_ c"
+    );
+    assert_eq!(
+        turndown.convert("<p>a <!-- note --> <em>b</em></p>"),
+        "a _b_"
+    );
+    assert_eq!(
+        turndown.convert("<p>a<span> </span><em>b</em></p>"),
+        "a _b_"
+    );
+}
+
+#[test]
+fn test_spacing_around_inline_elements_on_separate_lines() {
+    let turndown = Turndown::new();
+
+    // `)letter`: text starting on the line after a link
+    assert_eq!(
+        turndown.convert("<p>See <a href=\"/x\">here</a>\n  for details</p>"),
+        "See [here](/x) for details"
+    );
+    // `)|` and `|[`: links separated by a pipe on its own line
+    assert_eq!(
+        turndown.convert("<p><a href=\"/a\">A</a\n>\n|\n<a href=\"/b\">B</a></p>"),
+        "[A](/a) | [B](/b)"
+    );
+    // Number followed by a letter: superscripts and subscripts add no spacing of their own
+    assert_eq!(
+        turndown.convert("<p><sup>1</sup>\nWhen</p>"),
+        "<sup>1</sup> When"
+    );
+    assert_eq!(
+        turndown.convert("<p>H<sub>2</sub>O is 2<sup>nd</sup></p>"),
+        "H<sub>2</sub>O is 2<sup>nd</sup>"
+    );
+}
+
+/// Generates pseudo random numbers from a seed, so failing inputs can be reproduced
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+}
+
+const FUZZ_FRAGMENTS: &[&str] = &[
+    "<p>",
+    "</p>",
+    "<div class=\"note\">",
+    "</div>",
+    "<span>",
+    "</span>",
+    "<b>",
+    "</b>",
+    "<em>",
+    "</em>",
+    "<code>",
+    "</code>",
+    "<pre>",
+    "</pre>",
+    "<pre><code class=\"language-",
+    "<a href=\"https://example.com/",
+    "<a>",
+    "</a>",
+    "<img alt=\"",
+    "<img src=\"x\">",
+    "\">",
+    "<h1>",
+    "</h1>",
+    "<h6>",
+    "<blockquote>",
+    "</blockquote>",
+    "<ul>",
+    "<ol start=\"-3\">",
+    "<li>",
+    "<li value=\"99999999999999999999\">",
+    "<ol start=\"18446744073709551615\">",
+    "<li value=\"18446744073709551615\">",
+    "<li value=\"18446744073709551614\">",
+    "<span data-md-raw>",
+    "<div data-md-raw=\"**raw**\">",
+    "</li>",
+    "</ol>",
+    "</ul>",
+    "<table>",
+    "<tr>",
+    "<th>",
+    "<td colspan=\"0\">",
+    "<td colspan=\"4000000000\">",
+    "</table>",
+    "<br>",
+    "<wbr>",
+    "<hr>",
+    "<sup>",
+    "<sub>",
+    "<center>",
+    "<input type=\"checkbox\" checked>",
+    "<label for=\"x\">",
+    "<dl><dt>",
+    "<dd>",
+    "<svg><text>",
+    "</svg>",
+    "<math>",
+    "<script>",
+    "</script>",
+    "<style>",
+    "<!--",
+    "-->",
+    "<![CDATA[",
+    "]]>",
+    "<!DOCTYPE html>",
+    "</",
+    "<",
+    ">",
+    "&",
+    "&#",
+    "&#x1F980;",
+    "&amp",
+    "&nbsp;",
+    "\u{a0}",
+    "\u{3000}",
+    "\u{2028}",
+    "\u{200b}",
+    "\u{feff}",
+    "é",
+    "🦀",
+    "中文",
+    "\u{301}",
+    "#",
+    "* ",
+    "1. ",
+    "- ",
+    "+",
+    "`",
+    "```",
+    "~~~",
+    "|",
+    "\\",
+    "_",
+    "*",
+    "[",
+    "]",
+    "(",
+    ")",
+    "!",
+    "=",
+    "\n",
+    "\r\n",
+    " ",
+    "  ",
+    "\t",
+    "text",
+    "https://example.com/path",
+];
+
+/// Builds a document from random fragments, sometimes mixed with arbitrary bytes
+fn fuzz_input(random: &mut XorShift) -> String {
+    let length = random.next() % 60;
+    if random.next() % 4 == 0 {
+        let bytes: Vec<u8> = (0..length * 4).map(|_| random.next() as u8).collect();
+        return String::from_utf8_lossy(&bytes).into_owned();
+    }
+    (0..length)
+        .map(|_| FUZZ_FRAGMENTS[random.next() % FUZZ_FRAGMENTS.len()])
+        .collect()
+}
+
+#[test]
+fn test_convert_never_panics_on_malformed_input() {
+    use turndown::{CodeBlockStyle, IndentStyle, LinkStyle, TurndownOptions, WhitespaceMode};
+
+    let mut configurations = vec![
+        TurndownOptions::default(),
+        TurndownOptions {
+            link_style: LinkStyle::Referenced,
+            whitespace_mode: WhitespaceMode::PreserveLineBreaks,
+            code_block_style: CodeBlockStyle::Indented,
+            indent_style: IndentStyle::Tab,
+            heading_offset: i32::MIN,
+            emit_frontmatter: true,
+            max_alt_length: Some(0),
+            ..TurndownOptions::with_gfm()
+        },
+        TurndownOptions {
+            dedupe_manual_list_markers: true,
+            merge_adjacent_links: true,
+            reassemble_split_text: true,
+            autolink_bare_urls: true,
+            pandoc_fenced_divs: true,
+            heading_offset: i32::MAX,
+            emphasis_safety: true,
+            raw_markdown_attribute: Some("data-md-raw".to_string()),
+            svg_as_html: true,
+            complex_table_mode: turndown::ComplexTableMode::KeepHtml,
+            ..TurndownOptions::with_gfm()
+        },
+    ];
+    #[cfg(feature = "simple-parser")]
+    configurations.push(TurndownOptions {
+        parser: turndown::ParserKind::Simple,
+        ..TurndownOptions::with_gfm()
+    });
+
+    let converters: Vec<Turndown> = configurations
+        .drain(..)
+        .map(Turndown::with_options)
+        .collect();
+    let mut random = XorShift(0x2545_f491_4f6c_dd1d);
+    for _ in 0..300 {
+        let html = fuzz_input(&mut random);
+        for turndown in &converters {
+            turndown.convert(&html);
+        }
+    }
+
+    // Spans are capped at the 1000 columns HTML allows rather than allocated in full
+    let table = r#"<table><tr><th colspan="4000000000">a</th><th>b</th></tr></table>"#;
+    let markdown = Turndown::with_options(TurndownOptions::with_gfm()).convert(table);
+    assert_eq!(
+        markdown.lines().next().map(|row| row.matches('|').count()),
+        Some(1002)
+    );
+}